@@ -40,11 +40,11 @@ fn main() {
     // Each of calls is necessary to setup an execution engine which
     // compiles to native code.
     link_in_mc_jit();
-    initialize_native_target();
-    initialize_native_asm_printer();
+    initialize_native_target().unwrap();
+    initialize_native_asm_printer().unwrap();
 
     // Build an execution engine.
-    let ee = ExecutionEngine::create_execution_engine_for_module(module)
+    let (ee, _module) = ExecutionEngine::create_execution_engine_for_module(module)
         .expect("Failed to create execution engine:");
 
     let addr = ee.get_function_address(c"sum");
@@ -7,11 +7,11 @@ use crate::type_tag::*;
 use crate::*;
 
 impl<'c> Module<'c> {
-    pub fn verify(&self, action: LLVMVerifierFailureAction) -> Result<(), Message> {
+    pub fn verify(&self, action: LLVMVerifierFailureAction) -> Result<(), Error> {
         unsafe {
             let mut err = null_mut();
             if LLVMVerifyModule(self.as_raw(), action, &mut err) != 0 {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
             Ok(())
         }
@@ -6,21 +6,30 @@ use crate::owning::Owning;
 use crate::*;
 
 impl Context {
-    pub fn parse_bitcode(&self, mem_buf: &MemoryBuffer) -> Result<Owning<Module>, ()> {
+    pub fn parse_bitcode(&self, mem_buf: &MemoryBuffer) -> Result<Owning<Module>, Error> {
         unsafe {
             let mut ptr = null_mut();
             if LLVMParseBitcodeInContext2(self.as_raw(), mem_buf.as_raw(), &mut ptr) != 0 {
-                return Err(());
+                return Err(Error::Failed(format!(
+                    "parse bitcode of {} bytes, see the diagnostic handler of the context",
+                    mem_buf.get_size()
+                )));
             }
             Ok(Owning::from_raw(ptr))
         }
     }
 
-    pub fn get_bitcode_module(&self, mem_buf: Owning<MemoryBuffer>) -> Result<Owning<Module>, ()> {
+    pub fn get_bitcode_module(
+        &self,
+        mem_buf: Owning<MemoryBuffer>,
+    ) -> Result<Owning<Module>, Error> {
         unsafe {
             let mut ptr = null_mut();
+            let size = mem_buf.get_size();
             if LLVMGetBitcodeModuleInContext2(self.as_raw(), mem_buf.as_raw(), &mut ptr) != 0 {
-                return Err(());
+                return Err(Error::Failed(format!(
+                    "get bitcode module of {size} bytes, see the diagnostic handler of the context"
+                )));
             }
             Ok(Owning::from_raw(ptr))
         }
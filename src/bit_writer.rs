@@ -6,10 +6,10 @@ use crate::owning::Owning;
 use crate::*;
 
 impl<'c> Module<'c> {
-    pub fn write_bitcode_to_file(&self, path: &CStr) -> Result<(), ()> {
+    pub fn write_bitcode_to_file(&self, path: &CStr) -> Result<(), Error> {
         unsafe {
             if LLVMWriteBitcodeToFile(self.as_raw(), path.as_ptr()) != 0 {
-                Err(())
+                Err(Error::Failed(format!("write bitcode to {path:?}")))
             } else {
                 Ok(())
             }
@@ -21,20 +21,24 @@ impl<'c> Module<'c> {
         file_descriptor: i32,
         should_close: i32,
         unbuffer: i32,
-    ) -> Result<(), ()> {
+    ) -> Result<(), Error> {
         unsafe {
             if LLVMWriteBitcodeToFD(self.as_raw(), file_descriptor, should_close, unbuffer) != 0 {
-                Err(())
+                Err(Error::Failed(format!(
+                    "write bitcode to file descriptor {file_descriptor}"
+                )))
             } else {
                 Ok(())
             }
         }
     }
 
-    pub fn write_bitcode_to_file_handle(&self, handle: i32) -> Result<(), ()> {
+    pub fn write_bitcode_to_file_handle(&self, handle: i32) -> Result<(), Error> {
         unsafe {
             if LLVMWriteBitcodeToFileHandle(self.as_raw(), handle) != 0 {
-                Err(())
+                Err(Error::Failed(format!(
+                    "write bitcode to file handle {handle}"
+                )))
             } else {
                 Ok(())
             }
@@ -0,0 +1,77 @@
+//! Panic safety for Rust closures invoked from LLVM.
+//!
+//! Unwinding out of an `extern "C"` function is undefined behaviour, so every
+//! trampoline catches the panics of its closure. What happens next depends on
+//! who waits for the callback:
+//!
+//! - Callbacks LLVM runs before returning to the Rust caller keep the panic in
+//!   a [`PanicSlot`] of their context, resumed once the call returns.
+//! - Callbacks that can report a failure to LLVM turn the panic into one, see
+//!   [`catch_as_error`]. Materialization units fail their materialization.
+//! - The others may run on any thread, once the caller is gone, so they abort
+//!   the process, see [`catch_or_abort`]. The panic hook has already reported
+//!   the panic by then.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::process::abort;
+
+use crate::error::Error;
+
+/// A panic caught in a callback, to be resumed by the caller of LLVM.
+#[derive(Default)]
+pub(crate) struct PanicSlot {
+    payload: Cell<Option<Box<dyn Any + Send>>>,
+}
+
+impl PanicSlot {
+    /// Run `f`, returning `fallback()` if it panics. Only the first panic is
+    /// kept; LLVM may invoke further callbacks before returning.
+    pub(crate) fn catch<R>(&self, fallback: impl FnOnce() -> R, f: impl FnOnce() -> R) -> R {
+        match catch_unwind(AssertUnwindSafe(f)) {
+            Ok(r) => r,
+            Err(payload) => {
+                let first = self.payload.take().unwrap_or(payload);
+                self.payload.set(Some(first));
+                fallback()
+            }
+        }
+    }
+
+    /// Resume the panic caught, if any.
+    pub(crate) fn resume(self) {
+        if let Some(payload) = self.payload.into_inner() {
+            resume_unwind(payload)
+        }
+    }
+}
+
+/// The message of a panic, if it has one.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Run `f`, turning a panic into an error of the operation `what`.
+pub(crate) fn catch_as_error<R>(
+    what: &str,
+    f: impl FnOnce() -> Result<R, Error>,
+) -> Result<R, Error> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(Error::Failed(format!(
+            "{what}, it panicked: {}",
+            panic_message(&*payload)
+        )))
+    })
+}
+
+/// Run `f`, aborting the process if it panics.
+pub(crate) fn catch_or_abort<R>(f: impl FnOnce() -> R) -> R {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| abort())
+}
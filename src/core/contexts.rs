@@ -1,8 +1,12 @@
+use std::cell::RefCell;
 use std::ffi::{c_void, CStr};
+use std::mem::ManuallyDrop;
+use std::thread::panicking;
 
 use llvm_sys::core::*;
 use llvm_sys::*;
 
+use crate::callback::{catch_or_abort, PanicSlot};
 use crate::core::Message;
 use crate::owning::{OpaqueDrop, Owning};
 use crate::type_tag::*;
@@ -23,22 +27,59 @@ impl Context {
         unsafe { LLVMContextSetDiagnosticHandler(self.as_raw(), handle, handle_ctx as _) }
     }
 
-    /// Leak
-    pub fn set_diagnostic_handler_leak<T: Fn(&DiagnosticInfo) + 'static>(&self, handle: T) {
-        extern "C" fn handler_raw<T: Fn(&DiagnosticInfo) + 'static>(
+    /// Install `handle` for the lifetime of the context, leaking it.
+    ///
+    /// The handler is called on whichever thread reports a diagnostic, so it
+    /// must be `Send + Sync`.
+    #[deprecated(note = "leaks the handler, use `Context::set_diagnostic_handler_scoped`")]
+    pub fn set_diagnostic_handler_leak<T: Fn(&DiagnosticInfo) + Send + Sync + 'static>(
+        &self,
+        handle: T,
+    ) {
+        extern "C" fn handler_raw<T: Fn(&DiagnosticInfo) + Send + Sync + 'static>(
             info: *mut LLVMDiagnosticInfo,
             handle: *mut c_void,
         ) {
+            // Diagnostics may be reported on any thread using the context.
             let handle = handle as *mut T;
-            unsafe { (*handle)(DiagnosticInfo::from_raw(info)) }
+            catch_or_abort(|| unsafe { (*handle)(DiagnosticInfo::from_raw(info)) })
         }
         self.set_diagnostic_handler(Some(handler_raw::<T>), Box::into_raw(Box::new(handle)) as _);
     }
 
+    /// Install `handler` until the returned guard is dropped, which restores
+    /// the previous handler.
+    ///
+    /// `handler` is `'static` and `Send`, as forgetting the guard leaves it
+    /// installed. See [`Context::with_diagnostic_handler`] for handlers
+    /// borrowing locals.
+    pub fn set_diagnostic_handler_scoped<T: Fn(&DiagnosticInfo) + Send + 'static>(
+        &self,
+        handler: T,
+    ) -> DiagnosticHandlerGuard<'_, T> {
+        unsafe { DiagnosticHandlerGuard::new(self, handler) }
+    }
+
+    /// Install `handler` while running `body`, then restore the previous
+    /// handler. A panic of the handler is resumed once `body` returns.
+    ///
+    /// # Panics
+    ///
+    /// If `body` replaced the handler without restoring it.
+    pub fn with_diagnostic_handler<T: Fn(&DiagnosticInfo), R>(
+        &self,
+        handler: T,
+        body: impl FnOnce() -> R,
+    ) -> R {
+        // The guard is dropped before `handler` goes away, even if `body`
+        // panics, and `body` cannot move the context to another thread.
+        let _guard = unsafe { DiagnosticHandlerGuard::new(self, handler) };
+        body()
+    }
+
     pub fn get_diagnostic_handler(&self) -> LLVMDiagnosticHandler {
         unsafe { LLVMContextGetDiagnosticHandler(self.as_raw()) }
     }
-
     pub fn get_diagnostic_context(&self) -> *mut () {
         unsafe { LLVMContextGetDiagnosticContext(self.as_raw()) as _ }
     }
@@ -62,6 +103,78 @@ impl OpaqueDrop for LLVMContext {
     }
 }
 
+/// A diagnostic handler of a context, installed until the guard is dropped.
+/// Dropping the guard restores the previous handler, then resumes the first
+/// panic of the handler unless the thread is already panicking.
+///
+/// # Panics
+///
+/// On drop, if the handler was replaced and not restored. The handler is
+/// leaked then, as the one that replaced it may restore it later.
+#[must_use = "the handler is uninstalled when the guard is dropped"]
+pub struct DiagnosticHandlerGuard<'c, T: Fn(&DiagnosticInfo)> {
+    context: &'c Context,
+    handler: ManuallyDrop<Box<ScopedHandler<T>>>,
+    previous: LLVMDiagnosticHandler,
+    previous_ctx: *mut (),
+}
+
+struct ScopedHandler<T> {
+    handler: T,
+    panic: PanicSlot,
+}
+
+impl<'c, T: Fn(&DiagnosticInfo)> DiagnosticHandlerGuard<'c, T> {
+    /// # Safety
+    ///
+    /// The guard must be dropped before anything `handler` borrows, and while
+    /// it is alive the context must only report diagnostics on this thread.
+    unsafe fn new(context: &'c Context, handler: T) -> Self {
+        extern "C" fn handler_raw<T: Fn(&DiagnosticInfo)>(
+            info: *mut LLVMDiagnosticInfo,
+            handle: *mut c_void,
+        ) {
+            let handle = unsafe { &*(handle as *const ScopedHandler<T>) };
+            handle.panic.catch(
+                || (),
+                || (handle.handler)(unsafe { DiagnosticInfo::from_raw(info) }),
+            )
+        }
+        let this = Self {
+            context,
+            handler: ManuallyDrop::new(Box::new(ScopedHandler {
+                handler,
+                panic: PanicSlot::default(),
+            })),
+            previous: context.get_diagnostic_handler(),
+            previous_ctx: context.get_diagnostic_context(),
+        };
+        context.set_diagnostic_handler(Some(handler_raw::<T>), this.ctx());
+        this
+    }
+
+    fn ctx(&self) -> *mut () {
+        &**self.handler as *const ScopedHandler<T> as *mut ()
+    }
+}
+
+impl<T: Fn(&DiagnosticInfo)> Drop for DiagnosticHandlerGuard<'_, T> {
+    fn drop(&mut self) {
+        // Restoring the previous handler over another one would leave it
+        // installed once `handler` is gone.
+        assert!(
+            self.context.get_diagnostic_context() == self.ctx(),
+            "the diagnostic handler was replaced and not restored"
+        );
+        self.context
+            .set_diagnostic_handler(self.previous, self.previous_ctx);
+        let handler = unsafe { ManuallyDrop::take(&mut self.handler) };
+        if !panicking() {
+            handler.panic.resume();
+        }
+    }
+}
+
 impl DiagnosticInfo {
     pub fn get_description(&self) -> Message {
         unsafe { Message::from_raw(LLVMGetDiagInfoDescription(self.as_raw())) }
@@ -72,6 +185,57 @@ impl DiagnosticInfo {
     }
 }
 
+/// Records the diagnostics a context reports, see [`DiagnosticCollector::collect`].
+///
+/// Wrap the result of e.g. `parse_ir`, `link_modules` or `verify` with
+/// [`DiagnosticCollector::attach`] to report everything LLVM said about a failure.
+#[derive(Default, Debug)]
+pub struct DiagnosticCollector {
+    diagnostics: RefCell<Vec<(LLVMDiagnosticSeverity, String)>>,
+}
+
+impl DiagnosticCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the diagnostics of `context` while running `body`.
+    pub fn collect<R>(&self, context: &Context, body: impl FnOnce() -> R) -> R {
+        let handler = |info: &DiagnosticInfo| {
+            let description = info.get_description().to_string_lossy().into_owned();
+            self.diagnostics
+                .borrow_mut()
+                .push((info.get_severity(), description));
+        };
+        context.with_diagnostic_handler(handler, body)
+    }
+
+    /// Take the diagnostics recorded so far.
+    pub fn take(&self) -> Vec<(LLVMDiagnosticSeverity, String)> {
+        self.diagnostics.take()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .borrow()
+            .iter()
+            .any(|(severity, _)| *severity == LLVMDiagnosticSeverity::LLVMDSError)
+    }
+
+    /// Replace the error of a failed operation by the diagnostics recorded so
+    /// far, if there are any.
+    pub fn attach<R>(&self, result: Result<R, Error>) -> Result<R, Error> {
+        result.map_err(|e| {
+            let diagnostics = self.take();
+            if diagnostics.is_empty() {
+                e
+            } else {
+                Error::Diagnostics(diagnostics)
+            }
+        })
+    }
+}
+
 impl Context {
     pub fn get_md_kind_id(&self, name: &[u8]) -> u32 {
         unsafe { LLVMGetMDKindIDInContext(self.as_raw(), name.as_ptr() as _, name.len() as _) }
@@ -3,6 +3,8 @@ use std::ffi::CStr;
 use llvm_sys::core::*;
 use llvm_sys::*;
 
+use crate::core::values::constants::const_vector;
+use crate::core::values::function::lookup_intrinsic_id;
 use crate::owning::{OpaqueDrop, Owning};
 use crate::type_tag::*;
 use crate::*;
@@ -868,28 +870,48 @@ impl<'c> Builder<'c> {
         unsafe { Instruction::from_raw(LLVMBuildFree(self.as_raw(), pointer_val.as_raw())) }
     }
 
+    /// Build a `load`, failing if `access` has a release ordering.
     pub fn load<T: TypeTag, P: PtrTypeTag>(
         &self,
         ty: &Type<T>,
         pointer_val: &Value<P>,
+        access: MemAccess,
         name: &CStr,
-    ) -> &'c Instruction<T> {
+    ) -> Result<&'c Instruction<T>, Error> {
+        access.check(AccessKind::Load)?;
         unsafe {
-            Instruction::from_raw(LLVMBuildLoad2(
+            let inst = Instruction::from_raw(LLVMBuildLoad2(
                 self.as_raw(),
                 ty.as_raw(),
                 pointer_val.as_raw(),
                 name.as_ptr(),
-            ))
+            ));
+            if access.ordering != AtomicOrdering::NotAtomic {
+                inst.set_ordering(access.ordering.into());
+            }
+            access.apply(ty.get_context(), inst);
+            Ok(inst)
         }
     }
 
+    /// Build a `store`, failing if `access` has an acquire ordering or asks
+    /// for `!invariant.load`.
     pub fn store<T: TypeTag, P: PtrTypeTag>(
         &self,
         val: &Value<T>,
         ptr: &Value<P>,
-    ) -> &'c Instruction<void> {
-        unsafe { Instruction::from_raw(LLVMBuildStore(self.as_raw(), val.as_raw(), ptr.as_raw())) }
+        access: MemAccess,
+    ) -> Result<&'c Instruction<void>, Error> {
+        access.check(AccessKind::Store)?;
+        unsafe {
+            let inst =
+                Instruction::from_raw(LLVMBuildStore(self.as_raw(), val.as_raw(), ptr.as_raw()));
+            if access.ordering != AtomicOrdering::NotAtomic {
+                inst.set_ordering(access.ordering.into());
+            }
+            access.apply(val.get_type().get_context(), inst);
+            Ok(inst)
+        }
     }
 
     pub fn get_element_ptr<T: ElementTypeTag, P: PtrTypeTag, I: IntTypeTag>(
@@ -1476,12 +1498,12 @@ impl<'c> Builder<'c> {
         }
     }
 
-    pub fn extract_element<V: TypeTag, I: IntTypeTag>(
+    pub fn extract_element<V: VectorTypeTag, I: IntTypeTag>(
         &self,
         vec_val: &Value<V>,
         index: &Value<I>,
         name: &CStr,
-    ) -> &'c Instruction<any> {
+    ) -> &'c Instruction<V::ElementType> {
         unsafe {
             Instruction::from_raw(LLVMBuildExtractElement(
                 self.as_raw(),
@@ -1492,17 +1514,17 @@ impl<'c> Builder<'c> {
         }
     }
 
-    pub fn insert_element<A: TypeTag, E: TypeTag, I: IntTypeTag>(
+    pub fn insert_element<V: VectorTypeTag, I: IntTypeTag>(
         &self,
-        agg_val: &Value<A>,
-        elt_val: &Value<E>,
-        index: &Value<E>,
+        vec_val: &Value<V>,
+        elt_val: &Value<V::ElementType>,
+        index: &Value<I>,
         name: &CStr,
-    ) -> &'c Instruction<any> {
+    ) -> &'c Instruction<V> {
         unsafe {
             Instruction::from_raw(LLVMBuildInsertElement(
                 self.as_raw(),
-                agg_val.as_raw(),
+                vec_val.as_raw(),
                 elt_val.as_raw(),
                 index.as_raw(),
                 name.as_ptr(),
@@ -1510,11 +1532,11 @@ impl<'c> Builder<'c> {
         }
     }
 
-    pub fn shuffle_vector<V: TypeTag>(
+    pub fn shuffle_vector_raw<V: VectorTypeTag, M: VectorTypeTag>(
         &self,
         v1: &Value<V>,
         v2: &Value<V>,
-        mask: &Value<V>,
+        mask: &Value<M>,
         name: &CStr,
     ) -> &'c Instruction<any> {
         unsafe {
@@ -1528,6 +1550,28 @@ impl<'c> Builder<'c> {
         }
     }
 
+    pub fn shuffle_vector<T: TypeTag, const N: u32, const M: u32>(
+        &self,
+        v1: &Value<vector<T, N>>,
+        v2: &Value<vector<T, N>>,
+        mask: ShuffleMask<N, M>,
+        name: &CStr,
+    ) -> &'c Instruction<vector<T, M>> {
+        let i32_type = v1.get_type().get_context().i32_type();
+        let lanes = mask
+            .lanes()
+            .iter()
+            .map(|lane| -> &Value<int32> {
+                match lane {
+                    Some(lane) => i32_type.const_int(*lane as u64, false),
+                    None => i32_type.get_poison(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let mask = const_vector(&lanes);
+        unsafe { self.shuffle_vector_raw(v1, v2, mask, name).cast_unchecked() }
+    }
+
     pub fn extract_value<A: TypeTag>(
         &self,
         agg_val: &Value<A>,
@@ -1622,47 +1666,584 @@ impl<'c> Builder<'c> {
         }
     }
 
-    pub fn atomic_rmw<P: TypeTag, V: TypeTag>(
+    /// Build an `atomicrmw`, the ordering is taken from `access` and must be
+    /// at least monotonic.
+    pub fn atomic_rmw<P: PtrTypeTag, V: TypeTag>(
         &self,
-        op: LLVMAtomicRMWBinOp,
+        op: AtomicRmwOp,
         ptr: &Value<P>,
         val: &Value<V>,
-        ordering: LLVMAtomicOrdering,
-        single_thread: bool,
-    ) -> &'c Instruction<any> {
+        access: MemAccess,
+    ) -> Result<&'c Instruction<V>, Error> {
+        access.check(AccessKind::ReadModifyWrite)?;
         unsafe {
-            Instruction::from_raw(LLVMBuildAtomicRMW(
+            let inst = Instruction::from_raw(LLVMBuildAtomicRMW(
                 self.as_raw(),
-                op,
+                op.into(),
                 ptr.as_raw(),
                 val.as_raw(),
-                ordering,
-                single_thread as _,
-            ))
+                access.ordering.into(),
+                (access.sync_scope == SyncScope::SingleThread) as _,
+            ));
+            access.apply(val.get_type().get_context(), inst);
+            Ok(inst)
         }
     }
 
-    pub fn atomic_cmp_xchg<P: TypeTag, C: TypeTag, N: TypeTag>(
+    /// Build a `cmpxchg`, the success ordering is taken from `access` and must
+    /// be at least monotonic, the failure ordering must be too, and must not
+    /// have release semantics.
+    ///
+    /// The result is the loaded value paired with whether the exchange succeeded.
+    pub fn atomic_cmp_xchg<P: PtrTypeTag, T: TypeTag>(
         &self,
         ptr: &Value<P>,
-        cmp: &Value<C>,
-        new: &Value<N>,
-        success_ordering: LLVMAtomicOrdering,
-        failure_ordering: LLVMAtomicOrdering,
-        single_thread: bool,
-    ) -> &'c Instruction<any> {
-        unsafe {
-            Instruction::from_raw(LLVMBuildAtomicCmpXchg(
+        cmp: &Value<T>,
+        new: &Value<T>,
+        access: MemAccess,
+        failure_ordering: AtomicOrdering,
+    ) -> Result<&'c Instruction<structure<(T, int1)>>, Error> {
+        access.check(AccessKind::ReadModifyWrite)?;
+        if matches!(
+            failure_ordering,
+            AtomicOrdering::NotAtomic
+                | AtomicOrdering::Unordered
+                | AtomicOrdering::Release
+                | AtomicOrdering::AcquireRelease
+        ) {
+            return Err(Error::Message(format!(
+                "invalid cmpxchg failure ordering {failure_ordering:?}"
+            )));
+        }
+        unsafe {
+            let inst = Instruction::from_raw(LLVMBuildAtomicCmpXchg(
                 self.as_raw(),
                 ptr.as_raw(),
                 cmp.as_raw(),
                 new.as_raw(),
-                success_ordering,
-                failure_ordering,
-                single_thread as _,
+                access.ordering.into(),
+                failure_ordering.into(),
+                (access.sync_scope == SyncScope::SingleThread) as _,
+            ));
+            access.apply(cmp.get_type().get_context(), inst);
+            Ok(inst)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum AtomicOrdering {
+    #[default]
+    NotAtomic,
+    Unordered,
+    Monotonic,
+    Acquire,
+    Release,
+    AcquireRelease,
+    SequentiallyConsistent,
+}
+
+impl From<AtomicOrdering> for LLVMAtomicOrdering {
+    fn from(value: AtomicOrdering) -> Self {
+        match value {
+            AtomicOrdering::NotAtomic => Self::LLVMAtomicOrderingNotAtomic,
+            AtomicOrdering::Unordered => Self::LLVMAtomicOrderingUnordered,
+            AtomicOrdering::Monotonic => Self::LLVMAtomicOrderingMonotonic,
+            AtomicOrdering::Acquire => Self::LLVMAtomicOrderingAcquire,
+            AtomicOrdering::Release => Self::LLVMAtomicOrderingRelease,
+            AtomicOrdering::AcquireRelease => Self::LLVMAtomicOrderingAcquireRelease,
+            AtomicOrdering::SequentiallyConsistent => {
+                Self::LLVMAtomicOrderingSequentiallyConsistent
+            }
+        }
+    }
+}
+
+impl From<LLVMAtomicOrdering> for AtomicOrdering {
+    fn from(value: LLVMAtomicOrdering) -> Self {
+        match value {
+            LLVMAtomicOrdering::LLVMAtomicOrderingNotAtomic => Self::NotAtomic,
+            LLVMAtomicOrdering::LLVMAtomicOrderingUnordered => Self::Unordered,
+            LLVMAtomicOrdering::LLVMAtomicOrderingMonotonic => Self::Monotonic,
+            LLVMAtomicOrdering::LLVMAtomicOrderingAcquire => Self::Acquire,
+            LLVMAtomicOrdering::LLVMAtomicOrderingRelease => Self::Release,
+            LLVMAtomicOrdering::LLVMAtomicOrderingAcquireRelease => Self::AcquireRelease,
+            LLVMAtomicOrdering::LLVMAtomicOrderingSequentiallyConsistent => {
+                Self::SequentiallyConsistent
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AtomicRmwOp {
+    Xchg,
+    Add,
+    Sub,
+    And,
+    Nand,
+    Or,
+    Xor,
+    Max,
+    Min,
+    UMax,
+    UMin,
+    FAdd,
+    FSub,
+    FMax,
+    FMin,
+}
+
+impl From<AtomicRmwOp> for LLVMAtomicRMWBinOp {
+    fn from(value: AtomicRmwOp) -> Self {
+        match value {
+            AtomicRmwOp::Xchg => Self::LLVMAtomicRMWBinOpXchg,
+            AtomicRmwOp::Add => Self::LLVMAtomicRMWBinOpAdd,
+            AtomicRmwOp::Sub => Self::LLVMAtomicRMWBinOpSub,
+            AtomicRmwOp::And => Self::LLVMAtomicRMWBinOpAnd,
+            AtomicRmwOp::Nand => Self::LLVMAtomicRMWBinOpNand,
+            AtomicRmwOp::Or => Self::LLVMAtomicRMWBinOpOr,
+            AtomicRmwOp::Xor => Self::LLVMAtomicRMWBinOpXor,
+            AtomicRmwOp::Max => Self::LLVMAtomicRMWBinOpMax,
+            AtomicRmwOp::Min => Self::LLVMAtomicRMWBinOpMin,
+            AtomicRmwOp::UMax => Self::LLVMAtomicRMWBinOpUMax,
+            AtomicRmwOp::UMin => Self::LLVMAtomicRMWBinOpUMin,
+            AtomicRmwOp::FAdd => Self::LLVMAtomicRMWBinOpFAdd,
+            AtomicRmwOp::FSub => Self::LLVMAtomicRMWBinOpFSub,
+            AtomicRmwOp::FMax => Self::LLVMAtomicRMWBinOpFMax,
+            AtomicRmwOp::FMin => Self::LLVMAtomicRMWBinOpFMin,
+        }
+    }
+}
+
+impl From<LLVMAtomicRMWBinOp> for AtomicRmwOp {
+    fn from(value: LLVMAtomicRMWBinOp) -> Self {
+        match value {
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpXchg => Self::Xchg,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpAdd => Self::Add,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpSub => Self::Sub,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpAnd => Self::And,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpNand => Self::Nand,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpOr => Self::Or,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpXor => Self::Xor,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpMax => Self::Max,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpMin => Self::Min,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpUMax => Self::UMax,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpUMin => Self::UMin,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpFAdd => Self::FAdd,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpFSub => Self::FSub,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpFMax => Self::FMax,
+            LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpFMin => Self::FMin,
+        }
+    }
+}
+
+/// The scope an atomic operation synchronizes with.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum SyncScope {
+    /// Synchronize with every thread in the system.
+    #[default]
+    System,
+    /// Only synchronize with signal handlers running on the same thread.
+    SingleThread,
+}
+
+/// Options of a memory access, used by `load`, `store`, `atomic_rmw` and `atomic_cmp_xchg`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MemAccess {
+    /// Alignment in bytes, the ABI alignment of the type is used if it is `None`.
+    pub align: Option<u32>,
+    pub volatile: bool,
+    pub ordering: AtomicOrdering,
+    pub sync_scope: SyncScope,
+    /// Attach `!nontemporal`.
+    pub non_temporal: bool,
+    /// Attach `!invariant.load`, only valid for loads.
+    pub invariant_load: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum AccessKind {
+    Load,
+    Store,
+    ReadModifyWrite,
+}
+
+impl MemAccess {
+    pub fn atomic(ordering: AtomicOrdering) -> Self {
+        Self {
+            ordering,
+            ..Self::default()
+        }
+    }
+
+    /// Reject the options that are invalid IR for the kind of access.
+    fn check(&self, kind: AccessKind) -> Result<(), Error> {
+        use AtomicOrdering::*;
+        let invalid = |what: &str| Err(Error::Message(format!("invalid {kind:?} access: {what}")));
+        match (kind, self.ordering) {
+            (AccessKind::Load, Release | AcquireRelease) => invalid("release ordering"),
+            (AccessKind::Store, Acquire | AcquireRelease) => invalid("acquire ordering"),
+            (AccessKind::ReadModifyWrite, NotAtomic | Unordered) => {
+                invalid("ordering weaker than monotonic")
+            }
+            _ if self.invariant_load && kind != AccessKind::Load => {
+                invalid("`!invariant.load` only applies to loads")
+            }
+            (AccessKind::ReadModifyWrite, _) if self.non_temporal => {
+                invalid("`!nontemporal` only applies to loads and stores")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply<T: TypeTag>(&self, context: &Context, inst: &Instruction<T>) {
+        if let Some(align) = self.align {
+            inst.set_alignment(align);
+        }
+        if self.volatile {
+            inst.set_volatile(true);
+        }
+        if self.ordering != AtomicOrdering::NotAtomic {
+            inst.set_atomic_single_thread(self.sync_scope == SyncScope::SingleThread);
+        }
+        if self.non_temporal {
+            let one = context.i32_type().const_int(1, false);
+            // Wrapped as `ConstantAsMetadata`.
+            let one = unsafe { Metadata::from_raw(LLVMValueAsMetadata(one.as_raw())) };
+            let node = context.md_node(&[one]);
+            inst.set_metadata(
+                context.get_md_kind_id(b"nontemporal"),
+                Some(node.as_value(context)),
+            );
+        }
+        if self.invariant_load {
+            let node = context.md_node(&[]);
+            inst.set_metadata(
+                context.get_md_kind_id(b"invariant.load"),
+                Some(node.as_value(context)),
+            );
+        }
+    }
+}
+
+impl<'c> Builder<'c> {
+    /// Broadcast a scalar into every lane of a vector.
+    pub fn splat<T: TypeTag, const N: u32>(
+        &self,
+        value: &Value<T>,
+        name: &CStr,
+    ) -> &'c Instruction<vector<T, N>> {
+        let ty = value.get_type().vector_type::<N>();
+        let i32_type = ty.get_context().i32_type();
+        let poison = ty.get_poison();
+        unsafe {
+            let ptr = LLVMBuildInsertElement(
+                self.as_raw(),
+                poison.as_raw(),
+                value.as_raw(),
+                i32_type.const_int(0, false).as_raw(),
+                c"".as_ptr(),
+            );
+            Instruction::from_raw(LLVMBuildShuffleVector(
+                self.as_raw(),
+                ptr,
+                poison.as_raw(),
+                i32_type.vector_type::<N>().const_null().as_raw(),
+                name.as_ptr(),
             ))
         }
     }
+
+    /// The module the builder inserts into.
+    ///
+    /// Panics if the builder is not positioned in a block of a function.
+    fn insert_module(&self) -> &'c Module<'c> {
+        unsafe {
+            let block = LLVMGetInsertBlock(self.as_raw());
+            assert!(!block.is_null(), "the builder is not positioned");
+            let function = LLVMGetBasicBlockParent(block);
+            assert!(
+                !function.is_null(),
+                "the builder is positioned in a block outside of any function"
+            );
+            Module::from_raw(LLVMGetGlobalParent(function))
+        }
+    }
+
+    fn call_intrinsic_raw(
+        &self,
+        intrinsic: &[u8],
+        overloads: &[&Type<any>],
+        args: &[&Value<any>],
+        name: &CStr,
+    ) -> &'c Instruction<any> {
+        let module = self.insert_module();
+        unsafe {
+            let id = lookup_intrinsic_id(intrinsic);
+            let fun = module.get_intrinsic_declaration(id, overloads);
+            let fun_ty = module.context().intrinsic_get_type(id, overloads);
+            Instruction::from_raw(LLVMBuildCall2(
+                self.as_raw(),
+                fun_ty.as_raw(),
+                fun.as_raw(),
+                args.as_ptr() as _,
+                args.len() as _,
+                name.as_ptr(),
+            ))
+        }
+    }
+
+    fn vector_reduce_raw<V: VectorTypeTag>(
+        &self,
+        intrinsic: &[u8],
+        args: &[&Value<any>],
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        let overloads = [vec_val.get_type().to_any()];
+        unsafe {
+            self.call_intrinsic_raw(intrinsic, &overloads, args, name)
+                .cast_unchecked()
+        }
+    }
+
+    /// Call `llvm.vscale`, the runtime multiplier of scalable vector element counts.
+    ///
+    /// Panics if the builder is not positioned in a block of a function, as the
+    /// intrinsic is declared in its module.
+    pub fn vscale<const N: u32>(&self, ty: &Type<int<N>>, name: &CStr) -> &'c Instruction<int<N>> {
+        unsafe {
+            self.call_intrinsic_raw(b"llvm.vscale", &[ty.to_any()], &[], name)
+                .cast_unchecked()
+        }
+    }
+
+    /// Compute the runtime element count `vscale * MIN` of a scalable vector type.
+    ///
+    /// Panics like [`Builder::vscale`].
+    pub fn scalable_element_count<V: ScalableVectorTypeTag, const N: u32>(
+        &self,
+        vec_ty: &Type<V>,
+        ty: &Type<int<N>>,
+        name: &CStr,
+    ) -> &'c Instruction<int<N>> {
+        let vscale = self.vscale(ty, c"vscale");
+        self.nuw_mul(vscale, ty.const_int(vec_ty.get_size() as u64, false), name)
+    }
+
+    /// Call `llvm.vector.reduce.add`.
+    ///
+    /// Like the other reductions, this declares the intrinsic in the module of
+    /// the insertion point, so it panics if the builder is not positioned in a
+    /// block of a function.
+    pub fn vector_reduce_add<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.add",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_mul<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.mul",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_and<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.and",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_or<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(b"llvm.vector.reduce.or", &[vec_val.to_any()], vec_val, name)
+    }
+
+    pub fn vector_reduce_xor<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.xor",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_signed_max<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.smax",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_signed_min<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.smin",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_unsigned_max<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.umax",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_unsigned_min<V: VectorTypeTag<ElementType: IntMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.umin",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    /// Sequential floating-point sum of `start` and every lane, unless reassociation is allowed.
+    pub fn vector_reduce_float_add<V: VectorTypeTag<ElementType: FloatMathTypeTag>>(
+        &self,
+        start: &Value<V::ElementType>,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        let args = [start.to_any(), vec_val.to_any()];
+        self.vector_reduce_raw(b"llvm.vector.reduce.fadd", &args, vec_val, name)
+    }
+
+    /// Sequential floating-point product of `start` and every lane, unless reassociation is allowed.
+    pub fn vector_reduce_float_mul<V: VectorTypeTag<ElementType: FloatMathTypeTag>>(
+        &self,
+        start: &Value<V::ElementType>,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        let args = [start.to_any(), vec_val.to_any()];
+        self.vector_reduce_raw(b"llvm.vector.reduce.fmul", &args, vec_val, name)
+    }
+
+    pub fn vector_reduce_float_max<V: VectorTypeTag<ElementType: FloatMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.fmax",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+
+    pub fn vector_reduce_float_min<V: VectorTypeTag<ElementType: FloatMathTypeTag>>(
+        &self,
+        vec_val: &Value<V>,
+        name: &CStr,
+    ) -> &'c Instruction<V::ElementType> {
+        self.vector_reduce_raw(
+            b"llvm.vector.reduce.fmin",
+            &[vec_val.to_any()],
+            vec_val,
+            name,
+        )
+    }
+}
+
+/// A `shufflevector` mask that picks `M` lanes out of two `N` lane vectors.
+///
+/// `None` selects a poison lane.
+/// [`ShuffleMask::new`] is a `const fn`, so a mask defined as a `const` is checked at compile
+/// time, anywhere else it panics at runtime on an invalid mask, use [`ShuffleMask::try_new`]
+/// for masks computed at runtime.
+#[derive(Copy, Clone, Debug)]
+pub struct ShuffleMask<'a, const N: u32, const M: u32> {
+    lanes: &'a [Option<u32>],
+}
+
+impl<'a, const N: u32, const M: u32> ShuffleMask<'a, N, M> {
+    const fn check(lanes: &[Option<u32>]) -> Result<(), &'static str> {
+        if lanes.len() != M as usize {
+            return Err("mask length differs from the result length");
+        }
+        let mut i = 0;
+        while i < lanes.len() {
+            if let Some(lane) = lanes[i] {
+                if lane as u64 >= 2 * N as u64 {
+                    return Err("mask lane out of range");
+                }
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Panics if the mask does not have `M` lanes or a lane is out of range.
+    pub const fn new(lanes: &'a [Option<u32>]) -> Self {
+        if let Err(msg) = Self::check(lanes) {
+            panic!("{}", msg);
+        }
+        Self { lanes }
+    }
+
+    pub fn try_new(lanes: &'a [Option<u32>]) -> Result<Self, Error> {
+        match Self::check(lanes) {
+            Ok(()) => Ok(Self { lanes }),
+            Err(msg) => Err(Error::Message(msg.to_owned())),
+        }
+    }
+
+    pub fn lanes(&self) -> &'a [Option<u32>] {
+        self.lanes
+    }
 }
 
 impl<T: TypeTag> Instruction<T> {
@@ -1,4 +1,5 @@
 use std::ffi::CStr;
+use std::ops::Deref;
 use std::ptr::null_mut;
 
 use llvm_sys::core::*;
@@ -9,23 +10,23 @@ use crate::owning::{OpaqueDrop, Owning};
 use crate::*;
 
 impl MemoryBuffer {
-    pub fn create_with_contents_of_file(path: &CStr) -> Result<Owning<Self>, Message> {
+    pub fn create_with_contents_of_file(path: &CStr) -> Result<Owning<Self>, Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
             if LLVMCreateMemoryBufferWithContentsOfFile(path.as_ptr(), &mut ptr, &mut err) != 0 {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
             Ok(Owning::from_raw(ptr))
         }
     }
 
-    pub fn create_with_stdin() -> Result<Owning<Self>, Message> {
+    pub fn create_with_stdin() -> Result<Owning<Self>, Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
             if LLVMCreateMemoryBufferWithSTDIN(&mut ptr, &mut err) != 0 {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
             Ok(Owning::from_raw(ptr))
         }
@@ -58,6 +59,13 @@ impl MemoryBuffer {
         }
     }
 
+    /// Create a buffer over `data` without copying it. The returned wrapper
+    /// derefs to the buffer and keeps `data` alive.
+    pub fn from_vec(data: Vec<u8>) -> VecMemoryBuffer {
+        let buffer = unsafe { Self::create_with_memory_range(&*data, c"", false) };
+        VecMemoryBuffer { buffer, data }
+    }
+
     pub fn get_start(&self) -> *const u8 {
         unsafe { LLVMGetBufferStart(self.as_raw()) as *const u8 }
     }
@@ -65,6 +73,49 @@ impl MemoryBuffer {
     pub fn get_size(&self) -> usize {
         unsafe { LLVMGetBufferSize(self.as_raw()) }
     }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = self.get_size();
+        if size == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.get_start(), size) }
+    }
+}
+
+/// A memory buffer over the contents of a `Vec`, created by
+/// [`MemoryBuffer::from_vec`].
+///
+/// It derefs to its [`MemoryBuffer`], so it is passed without a copy wherever
+/// a `&MemoryBuffer` is expected. The buffer points into the `Vec`, so this
+/// wrapper must outlive every consumer of the buffer, which borrowing it
+/// ensures. LLVM cannot take ownership of the `Vec`, so functions taking an
+/// `Owning<MemoryBuffer>` need a copy, made by
+/// [`MemoryBuffer::create_with_memory_range_copy`].
+pub struct VecMemoryBuffer {
+    // Dropped before the data it points to.
+    buffer: Owning<MemoryBuffer>,
+    data: Vec<u8>,
+}
+
+impl VecMemoryBuffer {
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Deref for VecMemoryBuffer {
+    type Target = MemoryBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl AsRef<MemoryBuffer> for VecMemoryBuffer {
+    fn as_ref(&self) -> &MemoryBuffer {
+        &self.buffer
+    }
 }
 
 impl OpaqueDrop for LLVMMemoryBuffer {
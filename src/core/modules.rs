@@ -75,8 +75,8 @@ impl<'c> Module<'c> {
         unsafe { CStr::from_ptr(LLVMGetTarget(self.as_raw())) }
     }
 
-    pub fn set_target(&self, triple: &CStr) {
-        unsafe { LLVMSetTarget(self.as_raw(), triple.as_ptr()) }
+    pub fn set_target(&self, triple: impl AsRef<CStr>) {
+        unsafe { LLVMSetTarget(self.as_raw(), triple.as_ref().as_ptr()) }
     }
 
     pub fn copy_flags_metadata(&self) -> ModuleFlagsMetadata {
@@ -142,11 +142,11 @@ impl<'c> Module<'c> {
         unsafe { LLVMDumpModule(self.as_raw()) }
     }
 
-    pub fn print_to_file(&self, filename: &CStr) -> Result<(), Message> {
+    pub fn print_to_file(&self, filename: &CStr) -> Result<(), Error> {
         unsafe {
             let mut error = null_mut();
             if LLVMPrintModuleToFile(self.as_raw(), filename.as_ptr(), &mut error) != 0 {
-                Err(Message::from_raw(error))
+                Err(Message::from_raw(error).into())
             } else {
                 Ok(())
             }
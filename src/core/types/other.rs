@@ -1,7 +1,9 @@
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 
 use llvm_sys::core::*;
 
+use crate::opaque::Opaque;
+use crate::type_tag::*;
 use crate::*;
 
 impl Context {
@@ -30,7 +32,7 @@ impl Context {
         unsafe { Type::from_raw(LLVMMetadataTypeInContext(self.as_raw())) }
     }
 
-    pub fn target_ext_type(
+    pub fn target_ext_type_any(
         &self,
         name: &CStr,
         type_params: &[&Type<any>],
@@ -47,4 +49,86 @@ impl Context {
             ))
         }
     }
+
+    #[deprecated(note = "renamed to `target_ext_type_any`")]
+    pub fn target_ext_type(
+        &self,
+        name: &CStr,
+        type_params: &[&Type<any>],
+        int_params: &[u32],
+    ) -> &Type<target_ext_any> {
+        self.target_ext_type_any(name, type_params, int_params)
+    }
+
+    pub fn target_ext_type_of<N: TargetExtName>(
+        &self,
+        type_params: &[&Type<any>],
+        int_params: &[u32],
+    ) -> &Type<target_ext<N>> {
+        unsafe {
+            self.target_ext_type_any(N::NAME, type_params, int_params)
+                .cast_unchecked()
+        }
+    }
+}
+
+impl<T: TargetExtTypeTag> Type<T> {
+    pub fn to_target_ext_any(&self) -> &Type<target_ext_any> {
+        unsafe { self.cast_unchecked() }
+    }
+
+    /// Obtain the name and the integer parameters of a target extension type,
+    /// printing the type once. `None` if the printed form is not understood,
+    /// as the C API of LLVM 18 has no accessors for them.
+    pub fn get_target_ext_type_info(&self) -> Option<(CString, Vec<u32>)> {
+        parse_target_ext_type(self.print_to_string().to_bytes())
+    }
+
+    /// Obtain the name of a target extension type.
+    pub fn get_target_ext_type_name(&self) -> Option<CString> {
+        self.get_target_ext_type_info().map(|(name, _)| name)
+    }
+
+    /// Obtain the number of type parameters of a target extension type.
+    pub fn get_target_ext_type_num_type_params(&self) -> u32 {
+        self.get_num_contained_types()
+    }
+
+    /// Obtain the type parameters of a target extension type.
+    pub fn get_target_ext_type_type_params(&self) -> Vec<&Type<any>> {
+        let mut buffer = vec![None; self.get_num_contained_types() as usize];
+        self.get_subtypes(&mut buffer).to_vec()
+    }
+
+    /// Obtain the integer parameters of a target extension type.
+    pub fn get_target_ext_type_int_params(&self) -> Option<Vec<u32>> {
+        self.get_target_ext_type_info()
+            .map(|(_, int_params)| int_params)
+    }
+}
+
+/// The C API has no accessors for target extension types, so the name and the integer
+/// parameters are recovered from `target("name", types..., ints...)`. Types are not cached
+/// by address, as a disposed context may leave its addresses to another type.
+pub(crate) fn parse_target_ext_type(printed: &[u8]) -> Option<(CString, Vec<u32>)> {
+    let rest = printed.strip_prefix(b"target(\"")?.strip_suffix(b")")?;
+    let mut name = Vec::new();
+    let mut i = 0;
+    while *rest.get(i)? != b'"' {
+        if rest[i] == b'\\' {
+            let hex = std::str::from_utf8(rest.get(i + 1..i + 3)?).ok()?;
+            name.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            name.push(rest[i]);
+            i += 1;
+        }
+    }
+    let params = std::str::from_utf8(&rest[i + 1..]).ok()?;
+    let mut int_params = params
+        .rsplit(',')
+        .map_while(|x| x.trim().parse::<u32>().ok())
+        .collect::<Vec<_>>();
+    int_params.reverse();
+    Some((CString::new(name).ok()?, int_params))
 }
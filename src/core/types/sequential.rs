@@ -1,4 +1,5 @@
 use llvm_sys::core::*;
+use llvm_sys::LLVMTypeKind;
 
 use crate::type_tag::*;
 use crate::*;
@@ -9,12 +10,18 @@ impl<T: ArrayTypeTag> Type<T> {
     }
 }
 
-impl<T: VectorTypeTag> Type<T> {
+impl<T: FixedVectorTypeTag> Type<T> {
     pub fn to_vector_any(&self) -> &Type<vector_any> {
         unsafe { self.cast_unchecked() }
     }
 }
 
+impl<T: ScalableVectorTypeTag> Type<T> {
+    pub fn to_scalable_vector_any(&self) -> &Type<scalable_vector_any> {
+        unsafe { self.cast_unchecked() }
+    }
+}
+
 impl<T: SequentialTypeTag> Type<T> {
     pub fn element_type(&self) -> &Type<T::ElementType> {
         unsafe { Type::from_raw(LLVMGetElementType(self.as_raw())) }
@@ -78,12 +85,30 @@ impl<T: TypeTag> Type<T> {
         unsafe { Type::from_raw(LLVMVectorType(self.as_raw(), element_count)) }
     }
 
+    pub fn vector_type<const N: u32>(&self) -> &Type<vector<T, N>> {
+        unsafe { self.vector_type_any_count(N).cast_unchecked() }
+    }
+
     /// Create a vector type that contains a defined type and has a scalable number of elements.
-    pub fn scalable_vector_type_any_count(&self, element_count: u32) -> &Type<vector_any_len<T>> {
+    pub fn scalable_vector_type_any_count(
+        &self,
+        element_count: u32,
+    ) -> &Type<scalable_vector_any_len<T>> {
         unsafe { Type::from_raw(LLVMScalableVectorType(self.as_raw(), element_count)) }
     }
+
+    /// Create a vector type that contains a defined type and has `vscale * MIN` elements.
+    pub fn scalable_vector_type<const MIN: u32>(&self) -> &Type<scalable_vector<T, MIN>> {
+        unsafe { self.scalable_vector_type_any_count(MIN).cast_unchecked() }
+    }
 }
+
 impl<T: VectorTypeTag> Type<T> {
+    pub fn is_scalable(&self) -> bool {
+        self.get_kind() == LLVMTypeKind::LLVMScalableVectorTypeKind
+    }
+
+    /// Get the element count of a vector, the minimum element count for a scalable vector.
     pub fn get_size(&self) -> u32 {
         unsafe { LLVMGetVectorSize(self.as_raw()) }
     }
@@ -1,4 +1,5 @@
 use std::ffi::CStr;
+use std::mem::MaybeUninit;
 
 use llvm_sys::core::*;
 
@@ -23,6 +24,63 @@ impl Context {
     }
 }
 
+impl Context {
+    /// Create a literal struct type with typed elements.
+    pub fn structure_type<'c, Elements: TypeTuple<'c>>(
+        &'c self,
+        elements: Elements,
+    ) -> &'c Type<structure<Elements::Tags>> {
+        unsafe { self.structure_type_raw(elements, false).cast_unchecked() }
+    }
+
+    /// Create a packed literal struct type with typed elements.
+    pub fn packed_structure_type<'c, Elements: TypeTuple<'c>>(
+        &'c self,
+        elements: Elements,
+    ) -> &'c Type<structure<Elements::Tags, true>> {
+        unsafe { self.structure_type_raw(elements, true).cast_unchecked() }
+    }
+
+    fn structure_type_raw<'c, Elements: TypeTuple<'c>>(
+        &'c self,
+        elements: Elements,
+        packed: bool,
+    ) -> &'c Type<struct_any> {
+        let elements = elements.to_array_any();
+        unsafe {
+            Type::from_raw(LLVMStructTypeInContext(
+                self.as_raw(),
+                elements.as_ref().as_ptr() as _,
+                elements.as_ref().len() as _,
+                packed as _,
+            ))
+        }
+    }
+}
+
+impl<T: StructTypeTag> Type<T> {
+    pub fn to_struct_any(&self) -> &Type<struct_any> {
+        unsafe { self.cast_unchecked() }
+    }
+}
+
+impl<Elements: TagTuple, const PACKED: bool> Type<structure<Elements, PACKED>> {
+    /// Obtain the types of the struct's elements.
+    pub fn get_elements(&self) -> Elements::Types<'_> {
+        unsafe {
+            let mut array =
+                MaybeUninit::<<Elements::Types<'_> as Tuple>::Array<Option<&Type<any>>>>::zeroed()
+                    .assume_init();
+            self.to_struct_any()
+                .get_struct_element_types(array.as_mut());
+            Elements::Types::from_array_any_unchecked(std::mem::transmute::<
+                &[Option<&Type<any>>],
+                &[&Type<any>],
+            >(array.as_ref()))
+        }
+    }
+}
+
 impl Type<struct_any> {
     pub fn get_struct_name(&self) -> &CStr {
         unsafe { CStr::from_ptr(LLVMGetStructName(self.as_raw())) }
@@ -0,0 +1,455 @@
+//! A parsed representation of data layout strings.
+
+use std::ffi::CString;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::owning::Owning;
+use crate::target::TargetData;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mangling {
+    /// `e`, private symbols get a `.L` prefix.
+    Elf,
+    /// `l`, private symbols get a `@` prefix.
+    Goff,
+    /// `m`, private symbols get a `$` prefix.
+    Mips,
+    /// `o`, private symbols get an `L` prefix, others a `_` prefix.
+    MachO,
+    /// `x`, like `w`, with C symbols getting a `_` prefix.
+    WinCoffX86,
+    /// `w`, private symbols get a `.L` prefix.
+    WinCoff,
+    /// `a`, private symbols get a `L..` prefix.
+    XCoff,
+}
+
+impl Mangling {
+    fn from_char(c: &str) -> Option<Self> {
+        Some(match c {
+            "e" => Self::Elf,
+            "l" => Self::Goff,
+            "m" => Self::Mips,
+            "o" => Self::MachO,
+            "x" => Self::WinCoffX86,
+            "w" => Self::WinCoff,
+            "a" => Self::XCoff,
+            _ => return None,
+        })
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Self::Elf => 'e',
+            Self::Goff => 'l',
+            Self::Mips => 'm',
+            Self::MachO => 'o',
+            Self::WinCoffX86 => 'x',
+            Self::WinCoff => 'w',
+            Self::XCoff => 'a',
+        }
+    }
+}
+
+/// The alignments of the integer, float or vector type of a size, in bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TypeAlignment {
+    pub size: u32,
+    pub abi: u32,
+    pub preferred: u32,
+}
+
+/// The layout of the pointers of an address space, in bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PointerLayout {
+    pub address_space: u32,
+    pub size: u32,
+    pub abi: u32,
+    pub preferred: u32,
+    /// The size of the indices used in address computations.
+    pub index_size: u32,
+}
+
+/// The alignment of function pointers, in bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FunctionPointerAlignment {
+    /// Whether it is independent of the alignment of functions, rather than
+    /// a multiple of it.
+    pub independent: bool,
+    pub abi: u32,
+}
+
+/// A data layout, as the string of `TargetData::get_rep`.
+///
+/// Only the specifications present in the string are kept, the others take
+/// the defaults of LLVM. Lists are kept sorted, so equal layouts compare
+/// equal whatever the order of their specifications.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DataLayout {
+    pub endianness: Endianness,
+    /// The natural alignment of the stack, in bits.
+    pub stack_alignment: Option<u32>,
+    pub program_address_space: u32,
+    pub alloca_address_space: u32,
+    pub globals_address_space: u32,
+    pub function_pointer_alignment: Option<FunctionPointerAlignment>,
+    pub mangling: Option<Mangling>,
+    pub pointers: Vec<PointerLayout>,
+    pub integers: Vec<TypeAlignment>,
+    pub floats: Vec<TypeAlignment>,
+    pub vectors: Vec<TypeAlignment>,
+    /// The alignments of aggregates, whose size is 0.
+    pub aggregate: Option<TypeAlignment>,
+    pub native_integer_widths: Vec<u32>,
+    pub non_integral_address_spaces: Vec<u32>,
+}
+
+impl DataLayout {
+    pub fn from_target_data(td: &TargetData) -> Result<Self, Error> {
+        td.get_rep().to_string_lossy().parse()
+    }
+
+    pub fn to_target_data(&self) -> Owning<TargetData> {
+        TargetData::create(&CString::new(self.to_string()).unwrap())
+    }
+
+    /// The layout of the pointers of `address_space`, if specified.
+    pub fn pointer(&self, address_space: u32) -> Option<&PointerLayout> {
+        self.pointers
+            .iter()
+            .find(|pointer| pointer.address_space == address_space)
+    }
+
+    /// Check that modules of layout `other` can be linked into modules of this
+    /// layout. As for the linker of LLVM, an empty layout fits any, and other
+    /// layouts must be equal.
+    pub fn check_compatible(&self, other: &DataLayout) -> Result<(), Error> {
+        if self == other || *other == DataLayout::default() {
+            return Ok(());
+        }
+        let (this, that) = (self.to_string(), other.to_string());
+        let only_in = |a: &str, b: &str| {
+            a.split('-')
+                .filter(|spec| !b.split('-').any(|other| other == *spec))
+                .collect::<Vec<_>>()
+                .join("-")
+        };
+        Err(Error::Message(format!(
+            "data layout `{that}` is incompatible with `{this}`, they differ in `{}` and `{}`",
+            only_in(&that, &this),
+            only_in(&this, &that)
+        )))
+    }
+
+    fn sort(&mut self) {
+        self.pointers.sort_by_key(|pointer| pointer.address_space);
+        self.integers.sort_by_key(|ty| ty.size);
+        self.floats.sort_by_key(|ty| ty.size);
+        self.vectors.sort_by_key(|ty| ty.size);
+        self.native_integer_widths.sort();
+        self.non_integral_address_spaces.sort();
+    }
+}
+
+fn invalid(spec: &str) -> Error {
+    Error::Message(format!("invalid data layout specification `{spec}`"))
+}
+
+fn parse_numbers(spec: &str, fields: &str) -> Result<Vec<u32>, Error> {
+    fields
+        .split(':')
+        .map(|field| field.parse().map_err(|_| invalid(spec)))
+        .collect()
+}
+
+fn parse_alignment(spec: &str, size: &str, alignments: &str) -> Result<TypeAlignment, Error> {
+    let size = if size.is_empty() {
+        0
+    } else {
+        size.parse().map_err(|_| invalid(spec))?
+    };
+    match parse_numbers(spec, alignments)?[..] {
+        [abi] => Ok(TypeAlignment {
+            size,
+            abi,
+            preferred: abi,
+        }),
+        [abi, preferred] => Ok(TypeAlignment {
+            size,
+            abi,
+            preferred,
+        }),
+        _ => Err(invalid(spec)),
+    }
+}
+
+impl FromStr for DataLayout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut layout = DataLayout::default();
+        for spec in s.split('-').filter(|spec| !spec.is_empty()) {
+            if !spec.is_char_boundary(1) {
+                return Err(invalid(spec));
+            }
+            let (head, rest) = spec.split_at(1);
+            let number = || rest.parse::<u32>().map_err(|_| invalid(spec));
+            match head {
+                "e" if rest.is_empty() => layout.endianness = Endianness::Little,
+                "E" if rest.is_empty() => layout.endianness = Endianness::Big,
+                "S" => layout.stack_alignment = Some(number()?),
+                "P" => layout.program_address_space = number()?,
+                "A" => layout.alloca_address_space = number()?,
+                "G" => layout.globals_address_space = number()?,
+                "F" => {
+                    let (independent, abi) = if let Some(abi) = rest.strip_prefix('i') {
+                        (true, abi)
+                    } else if let Some(abi) = rest.strip_prefix('n') {
+                        (false, abi)
+                    } else {
+                        return Err(invalid(spec));
+                    };
+                    let abi = abi.parse().map_err(|_| invalid(spec))?;
+                    layout.function_pointer_alignment =
+                        Some(FunctionPointerAlignment { independent, abi });
+                }
+                "m" => {
+                    let mangling = rest.strip_prefix(':').and_then(Mangling::from_char);
+                    layout.mangling = Some(mangling.ok_or_else(|| invalid(spec))?);
+                }
+                "n" if rest.starts_with("i:") => {
+                    layout.non_integral_address_spaces = parse_numbers(spec, &rest[2..])?;
+                }
+                "n" => layout.native_integer_widths = parse_numbers(spec, rest)?,
+                "p" => {
+                    let (address_space, fields) =
+                        rest.split_once(':').ok_or_else(|| invalid(spec))?;
+                    let address_space = if address_space.is_empty() {
+                        0
+                    } else {
+                        address_space.parse().map_err(|_| invalid(spec))?
+                    };
+                    let (size, abi, preferred, index_size) = match parse_numbers(spec, fields)?[..]
+                    {
+                        [size, abi] => (size, abi, abi, size),
+                        [size, abi, preferred] => (size, abi, preferred, size),
+                        [size, abi, preferred, index_size] => (size, abi, preferred, index_size),
+                        _ => return Err(invalid(spec)),
+                    };
+                    layout
+                        .pointers
+                        .retain(|pointer| pointer.address_space != address_space);
+                    layout.pointers.push(PointerLayout {
+                        address_space,
+                        size,
+                        abi,
+                        preferred,
+                        index_size,
+                    });
+                }
+                "i" | "f" | "v" | "a" => {
+                    let (size, alignments) = rest.split_once(':').ok_or_else(|| invalid(spec))?;
+                    let alignment = parse_alignment(spec, size, alignments)?;
+                    let list = match head {
+                        "i" => &mut layout.integers,
+                        "f" => &mut layout.floats,
+                        "v" => &mut layout.vectors,
+                        _ => {
+                            if alignment.size != 0 {
+                                return Err(invalid(spec));
+                            }
+                            layout.aggregate = Some(alignment);
+                            continue;
+                        }
+                    };
+                    list.retain(|ty| ty.size != alignment.size);
+                    list.push(alignment);
+                }
+                _ => return Err(invalid(spec)),
+            }
+        }
+        layout.sort();
+        Ok(layout)
+    }
+}
+
+fn write_list(f: &mut Formatter<'_>, numbers: &[u32]) -> std::fmt::Result {
+    for (i, number) in numbers.iter().enumerate() {
+        if i != 0 {
+            f.write_str(":")?;
+        }
+        write!(f, "{number}")?;
+    }
+    Ok(())
+}
+
+fn write_alignment(f: &mut Formatter<'_>, head: &str, ty: &TypeAlignment) -> std::fmt::Result {
+    write!(f, "-{head}")?;
+    if head != "a" {
+        write!(f, "{}", ty.size)?;
+    }
+    write!(f, ":{}", ty.abi)?;
+    if ty.preferred != ty.abi {
+        write!(f, ":{}", ty.preferred)?;
+    }
+    Ok(())
+}
+
+impl Display for DataLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self.endianness {
+            Endianness::Little => "e",
+            Endianness::Big => "E",
+        })?;
+        if let Some(mangling) = self.mangling {
+            write!(f, "-m:{}", mangling.as_char())?;
+        }
+        for pointer in &self.pointers {
+            f.write_str("-p")?;
+            if pointer.address_space != 0 {
+                write!(f, "{}", pointer.address_space)?;
+            }
+            write!(f, ":{}:{}", pointer.size, pointer.abi)?;
+            if pointer.preferred != pointer.abi || pointer.index_size != pointer.size {
+                write!(f, ":{}", pointer.preferred)?;
+            }
+            if pointer.index_size != pointer.size {
+                write!(f, ":{}", pointer.index_size)?;
+            }
+        }
+        for ty in &self.integers {
+            write_alignment(f, "i", ty)?;
+        }
+        for ty in &self.floats {
+            write_alignment(f, "f", ty)?;
+        }
+        for ty in &self.vectors {
+            write_alignment(f, "v", ty)?;
+        }
+        if let Some(ty) = &self.aggregate {
+            write_alignment(f, "a", ty)?;
+        }
+        if let Some(alignment) = self.function_pointer_alignment {
+            let kind = if alignment.independent { 'i' } else { 'n' };
+            write!(f, "-F{kind}{}", alignment.abi)?;
+        }
+        if !self.native_integer_widths.is_empty() {
+            f.write_str("-n")?;
+            write_list(f, &self.native_integer_widths)?;
+        }
+        if !self.non_integral_address_spaces.is_empty() {
+            f.write_str("-ni:")?;
+            write_list(f, &self.non_integral_address_spaces)?;
+        }
+        if let Some(alignment) = self.stack_alignment {
+            write!(f, "-S{alignment}")?;
+        }
+        if self.program_address_space != 0 {
+            write!(f, "-P{}", self.program_address_space)?;
+        }
+        if self.alloca_address_space != 0 {
+            write!(f, "-A{}", self.alloca_address_space)?;
+        }
+        if self.globals_address_space != 0 {
+            write!(f, "-G{}", self.globals_address_space)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X86_64: &str =
+        "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128";
+    const AARCH64: &str = "e-m:e-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128";
+    const AMDGPU: &str = "e-p:64:64-p1:64:64-p2:32:32-p3:32:32-p4:64:64-p5:32:32-p6:32:32-p7:160:256:256:32-p8:128:128-i64:64-v16:16-v24:32-v32:32-v48:64-v96:128-v192:256-v256:256-v512:512-v1024:1024-v2048:2048-n32:64-S32-A5-G1-ni:7:8";
+
+    #[test]
+    fn x86_64_round_trips() {
+        let layout = X86_64.parse::<DataLayout>().unwrap();
+        assert_eq!(layout.to_string(), X86_64);
+        assert_eq!(layout.mangling, Some(Mangling::Elf));
+        assert_eq!(layout.pointer(270).unwrap().size, 32);
+        assert_eq!(layout.native_integer_widths, [8, 16, 32, 64]);
+        assert_eq!(layout.stack_alignment, Some(128));
+    }
+
+    #[test]
+    fn aarch64_round_trips() {
+        let layout = AARCH64.parse::<DataLayout>().unwrap();
+        assert_eq!(layout.to_string(), AARCH64);
+        assert_eq!(
+            layout.integers[0],
+            TypeAlignment {
+                size: 8,
+                abi: 8,
+                preferred: 32
+            }
+        );
+        assert!(layout.pointers.is_empty());
+    }
+
+    #[test]
+    fn non_integral_address_spaces_round_trip() {
+        let layout = AMDGPU.parse::<DataLayout>().unwrap();
+        assert_eq!(layout.non_integral_address_spaces, [7, 8]);
+        assert_eq!(
+            layout.pointer(7),
+            Some(&PointerLayout {
+                address_space: 7,
+                size: 160,
+                abi: 256,
+                preferred: 256,
+                index_size: 32
+            })
+        );
+        assert_eq!(layout.alloca_address_space, 5);
+        assert_eq!(layout.globals_address_space, 1);
+        // The specifications are written in a fixed order.
+        let written = layout.to_string();
+        assert!(written.ends_with("-n32:64-ni:7:8-S32-A5-G1"));
+        assert_eq!(written.parse::<DataLayout>().unwrap(), layout);
+    }
+
+    #[test]
+    fn specification_order_does_not_matter() {
+        let reordered = "e-S128-n32:64-i128:128-i64:64-i16:16:32-i8:8:32-m:e";
+        assert_eq!(
+            reordered.parse::<DataLayout>().unwrap(),
+            AARCH64.parse::<DataLayout>().unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        for invalid in [
+            "x", "é", "Fé8", "F", "Fx8", "Fi", "m:q", "p:64", "i64", "a8:8", "e1", "S",
+        ] {
+            assert!(invalid.parse::<DataLayout>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn compatibility() {
+        let x86_64 = X86_64.parse::<DataLayout>().unwrap();
+        let aarch64 = AARCH64.parse::<DataLayout>().unwrap();
+        x86_64.check_compatible(&x86_64).unwrap();
+        x86_64.check_compatible(&DataLayout::default()).unwrap();
+        let Err(Error::Message(message)) = x86_64.check_compatible(&aarch64) else {
+            panic!("x86_64 and aarch64 layouts are compatible");
+        };
+        assert!(message.contains("i8:8:32"));
+        assert!(message.contains("f80:128"));
+    }
+}
@@ -76,10 +76,13 @@ impl DisasmContext {
         }
     }
 
-    pub fn set_options(&self, options: u64) -> Result<(), ()> {
+    /// Set the disassembler's options; fails if any of them was not recognized.
+    pub fn set_options(&self, options: u64) -> Result<(), Error> {
         unsafe {
-            if LLVMSetDisasmOptions(self.as_raw(), options) != 0 {
-                Err(())
+            if LLVMSetDisasmOptions(self.as_raw(), options) == 0 {
+                Err(Error::Failed(format!(
+                    "set disassembler options {options:#x}"
+                )))
             } else {
                 Ok(())
             }
@@ -1,11 +1,13 @@
 use std::borrow::Borrow;
-use std::ffi::{c_char, CStr};
-use std::fmt::{Debug, Formatter};
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::{Debug, Display, Formatter};
 use std::ops::Deref;
 use std::ptr::NonNull;
 
 use llvm_sys::error::*;
+use llvm_sys::LLVMDiagnosticSeverity;
 
+use crate::core::Message;
 use crate::owning::{OpaqueDrop, Owning};
 use crate::{Opaque, PhantomOpaque};
 
@@ -14,25 +16,26 @@ pub fn string_error_type_id() -> LLVMErrorTypeId {
 }
 
 #[repr(transparent)]
-pub struct Error {
+pub struct OpaqueError {
     _opaque: PhantomOpaque,
 }
 
-unsafe impl Opaque for Error {
+unsafe impl Opaque for OpaqueError {
     type Inner = LLVMOpaqueError;
 }
 
-impl Error {
-    pub unsafe fn check(ptr: *mut LLVMOpaqueError) -> Result<(), Owning<Self>> {
-        if let Some(e) = unsafe { Owning::from_ptr(ptr) } {
-            Err(e)
+impl OpaqueError {
+    /// Consume an `LLVMErrorRef` returned by the C API, mapping success to `Ok`.
+    pub unsafe fn check(ptr: *mut LLVMOpaqueError) -> Result<(), Error> {
+        if let Some(e) = unsafe { Owning::<Self>::from_ptr(ptr) } {
+            Err(e.into())
         } else {
             Ok(())
         }
     }
 }
 
-impl Error {
+impl OpaqueError {
     pub fn get_type_id(&self) -> LLVMErrorTypeId {
         unsafe { LLVMGetErrorTypeId(self.as_raw()) }
     }
@@ -44,7 +47,7 @@ impl OpaqueDrop for LLVMOpaqueError {
     }
 }
 
-impl Error {
+impl OpaqueError {
     pub fn get_message(&self) -> ErrorMessage {
         unsafe { ErrorMessage::from_raw(LLVMGetErrorMessage(self.as_raw())) }
     }
@@ -56,15 +59,15 @@ impl Drop for ErrorMessage {
     }
 }
 
-impl Error {
+impl OpaqueError {
     pub fn create_string_error(err_msg: &CStr) -> Owning<Self> {
         unsafe { Owning::from_raw(LLVMCreateStringError(err_msg.as_ptr())) }
     }
 }
 
-impl Debug for Error {
+impl Debug for OpaqueError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut s = f.debug_struct("Error");
+        let mut s = f.debug_struct("OpaqueError");
         s.field("type_id", &self.get_type_id());
         s.field("message", &self.get_message());
         s.finish()
@@ -111,3 +114,129 @@ impl ErrorMessage {
         self.ptr.as_ptr() as _
     }
 }
+
+/// The error type returned by every fallible operation of this crate.
+///
+/// LLVM reports failures in several shapes: a bare status code, a message
+/// allocated with `LLVMCreateMessage`, the global `libLTO` error string, or an
+/// `LLVMErrorRef`. All of them are converted into owned data, so the error is
+/// `Send + Sync` and composes with `?` and `std::error::Error` based crates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The operation, described with its arguments, failed without LLVM
+    /// giving any further detail.
+    Failed(String),
+    /// A diagnostic message returned by the C API.
+    Message(String),
+    /// An error reported by `libLTO` through `lto_get_error_message`.
+    Lto(String),
+    /// An `LLVMErrorRef`; the payload is available as the error source.
+    Llvm(ErrorInfo),
+    /// The `(severity, description)` pairs reported to a diagnostic handler,
+    /// see [`DiagnosticCollector`](crate::core::contexts::DiagnosticCollector).
+    Diagnostics(Vec<(LLVMDiagnosticSeverity, String)>),
+}
+
+impl Error {
+    /// Convert back into an `LLVMErrorRef`, e.g. to return it from a callback.
+    ///
+    /// The original error class is not preserved: the result is always a string
+    /// error carrying the message.
+    pub fn into_opaque(self) -> Owning<OpaqueError> {
+        OpaqueError::create_string_error(&self.into_c_string())
+    }
+
+    /// Convert into a message allocated by LLVM, e.g. to store it through a
+    /// `char **ErrMsg` out parameter.
+    pub fn into_message(self) -> Message {
+        Message::create(&self.into_c_string())
+    }
+
+    fn into_c_string(self) -> CString {
+        let message = match self {
+            Error::Llvm(info) => info.message,
+            e => e.to_string(),
+        };
+        CString::new(message.replace('\0', " ")).unwrap()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Failed(operation) => write!(f, "failed to {operation}"),
+            Error::Message(message) => f.write_str(message),
+            Error::Lto(message) => write!(f, "LTO error: {message}"),
+            Error::Llvm(info) => write!(f, "LLVM error: {}", info.message),
+            Error::Diagnostics(diagnostics) => {
+                for (i, (severity, description)) in diagnostics.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str("\n")?;
+                    }
+                    let severity = match severity {
+                        LLVMDiagnosticSeverity::LLVMDSError => "error",
+                        LLVMDiagnosticSeverity::LLVMDSWarning => "warning",
+                        LLVMDiagnosticSeverity::LLVMDSRemark => "remark",
+                        LLVMDiagnosticSeverity::LLVMDSNote => "note",
+                    };
+                    write!(f, "{severity}: {description}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Llvm(info) => Some(info),
+            _ => None,
+        }
+    }
+}
+
+impl From<Message> for Error {
+    fn from(value: Message) -> Self {
+        Error::Message(value.to_string_lossy().into_owned())
+    }
+}
+
+impl From<Owning<OpaqueError>> for Error {
+    fn from(value: Owning<OpaqueError>) -> Self {
+        Error::Llvm(ErrorInfo {
+            type_id: value.get_type_id() as usize,
+            message: value.get_message().to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// The contents of a consumed `LLVMErrorRef`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorInfo {
+    type_id: usize,
+    message: String,
+}
+
+impl ErrorInfo {
+    pub fn get_type_id(&self) -> LLVMErrorTypeId {
+        self.type_id as _
+    }
+
+    /// Returns whether the error was created by `LLVMCreateStringError`.
+    pub fn is_string_error(&self) -> bool {
+        self.type_id == string_error_type_id() as usize
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for ErrorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ErrorInfo {}
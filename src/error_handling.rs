@@ -3,6 +3,8 @@ use std::mem::{forget, size_of, MaybeUninit};
 
 use llvm_sys::error_handling::*;
 
+use crate::callback::catch_or_abort;
+
 #[allow(clippy::uninit_assumed_init)]
 pub fn install_fatal_error_handler<T: Fn(&CStr) + 'static>(handle: T) {
     assert_eq!(
@@ -12,11 +14,13 @@ pub fn install_fatal_error_handler<T: Fn(&CStr) + 'static>(handle: T) {
     );
     forget(handle);
     extern "C" fn handler_raw<T: Fn(&CStr) + 'static>(reason: *const c_char) {
-        unsafe {
+        // LLVM exits right after the handler returns, so a panic can't be
+        // resumed; abort instead of unwinding into C++.
+        catch_or_abort(|| unsafe {
             let handle = MaybeUninit::<T>::uninit().assume_init();
             handle(CStr::from_ptr(reason));
             forget(handle);
-        }
+        })
     }
     install_fatal_error_handler_raw(Some(handler_raw::<T>));
 }
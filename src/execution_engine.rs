@@ -1,12 +1,15 @@
 use std::ffi::{c_char, c_void, CStr};
 use std::marker::PhantomData;
 use std::mem::size_of;
+use std::ops::Deref;
 use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use llvm_sys::execution_engine::*;
 use llvm_sys::prelude::*;
 use llvm_sys::target_machine::*;
 
+use crate::callback::catch_or_abort;
 use crate::core::Message;
 use crate::owning::{OpaqueDrop, Owning};
 use crate::target::TargetData;
@@ -82,6 +85,164 @@ impl GenericValue {
     }
 }
 
+/// A type tag whose values the interpreter passes as [`GenericValue`]s, with
+/// the Rust type they convert to.
+pub trait GenericTypeTag: TypeTag {
+    type Rust;
+
+    fn to_generic_value(ty: &Type<Self>, value: Self::Rust) -> Owning<GenericValue>;
+
+    fn from_generic_value(ty: &Type<Self>, value: &GenericValue) -> Self::Rust;
+}
+
+impl GenericTypeTag for int1 {
+    type Rust = bool;
+
+    fn to_generic_value(ty: &Type<Self>, value: bool) -> Owning<GenericValue> {
+        GenericValue::create_int(ty, value as u64, false)
+    }
+
+    fn from_generic_value(_ty: &Type<Self>, value: &GenericValue) -> bool {
+        value.to_int(false) != 0
+    }
+}
+
+macro_rules! impl_generic_int {
+    ($($tag:ty => $rust:ty),*) => {
+        $(
+            impl GenericTypeTag for $tag {
+                type Rust = $rust;
+
+                fn to_generic_value(ty: &Type<Self>, value: $rust) -> Owning<GenericValue> {
+                    GenericValue::create_int(ty, value as u64, true)
+                }
+
+                fn from_generic_value(_ty: &Type<Self>, value: &GenericValue) -> $rust {
+                    value.to_int(true) as $rust
+                }
+            }
+        )*
+    };
+}
+
+impl_generic_int!(int8 => i8, int16 => i16, int32 => i32, int64 => i64);
+
+impl GenericTypeTag for float {
+    type Rust = f32;
+
+    fn to_generic_value(ty: &Type<Self>, value: f32) -> Owning<GenericValue> {
+        GenericValue::create_float(ty, value as f64)
+    }
+
+    fn from_generic_value(ty: &Type<Self>, value: &GenericValue) -> f32 {
+        value.to_float(ty) as f32
+    }
+}
+
+impl GenericTypeTag for double {
+    type Rust = f64;
+
+    fn to_generic_value(ty: &Type<Self>, value: f64) -> Owning<GenericValue> {
+        GenericValue::create_float(ty, value)
+    }
+
+    fn from_generic_value(ty: &Type<Self>, value: &GenericValue) -> f64 {
+        value.to_float(ty)
+    }
+}
+
+impl<const ADDRESS_SPACE: u32> GenericTypeTag for ptr<ADDRESS_SPACE> {
+    type Rust = *mut ();
+
+    fn to_generic_value(_ty: &Type<Self>, value: *mut ()) -> Owning<GenericValue> {
+        GenericValue::create_pointer(value)
+    }
+
+    fn from_generic_value(_ty: &Type<Self>, value: &GenericValue) -> *mut () {
+        value.to_pointer()
+    }
+}
+
+/// A type tag the interpreter can return, like [`GenericTypeTag`] but
+/// including `void`.
+pub trait GenericReturnTypeTag: TypeTag {
+    type Rust;
+
+    fn from_generic_return(ty: &Type<Self>, value: &GenericValue) -> Self::Rust;
+}
+
+impl<T: GenericTypeTag> GenericReturnTypeTag for T {
+    type Rust = T::Rust;
+
+    fn from_generic_return(ty: &Type<Self>, value: &GenericValue) -> Self::Rust {
+        T::from_generic_value(ty, value)
+    }
+}
+
+impl GenericReturnTypeTag for void {
+    type Rust = ();
+
+    fn from_generic_return(_ty: &Type<Self>, _value: &GenericValue) {}
+}
+
+/// A tuple of [`GenericTypeTag`]s, for the parameters of a function.
+pub trait GenericTagTuple: TagTuple {
+    type Rust;
+
+    fn to_generic_values(types: Self::Types<'_>, values: Self::Rust) -> Vec<Owning<GenericValue>>;
+}
+
+macro_rules! impl_generic_tag_tuple {
+    ($(($arg:ident, $ty:ident, $value:ident)),*) => {
+        impl<$($arg: GenericTypeTag),*> GenericTagTuple for ($($arg,)*) {
+            type Rust = ($($arg::Rust,)*);
+
+            fn to_generic_values(
+                types: Self::Types<'_>,
+                values: Self::Rust,
+            ) -> Vec<Owning<GenericValue>> {
+                let ($($ty,)*) = types;
+                let ($($value,)*) = values;
+                vec![$($arg::to_generic_value($ty, $value)),*]
+            }
+        }
+    };
+}
+
+impl_generic_tag_tuple!();
+impl_generic_tag_tuple!((A, a, va));
+impl_generic_tag_tuple!((A, a, va), (B, b, vb));
+impl_generic_tag_tuple!((A, a, va), (B, b, vb), (C, c, vc));
+impl_generic_tag_tuple!((A, a, va), (B, b, vb), (C, c, vc), (D, d, vd));
+impl_generic_tag_tuple!((A, a, va), (B, b, vb), (C, c, vc), (D, d, vd), (E, e, ve));
+impl_generic_tag_tuple!(
+    (A, a, va),
+    (B, b, vb),
+    (C, c, vc),
+    (D, d, vd),
+    (E, e, ve),
+    (F, f, vf)
+);
+impl_generic_tag_tuple!(
+    (A, a, va),
+    (B, b, vb),
+    (C, c, vc),
+    (D, d, vd),
+    (E, e, ve),
+    (F, f, vf),
+    (G, g, vg)
+);
+impl_generic_tag_tuple!(
+    (A, a, va),
+    (B, b, vb),
+    (C, c, vc),
+    (D, d, vd),
+    (E, e, ve),
+    (F, f, vf),
+    (G, g, vg),
+    (H, h, vh)
+);
+
 impl OpaqueDrop for LLVMOpaqueGenericValue {
     unsafe fn drop_raw(ptr: *mut Self) {
         unsafe { LLVMDisposeGenericValue(ptr) }
@@ -91,46 +252,190 @@ impl OpaqueDrop for LLVMOpaqueGenericValue {
 impl<'c> ExecutionEngine<'c> {
     pub fn create_execution_engine_for_module(
         module: Owning<Module<'c>>,
-    ) -> Result<Owning<Self>, Message> {
+    ) -> Result<(OwnedExecutionEngine<'c>, ModuleHandle<'c>), Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
-            if LLVMCreateExecutionEngineForModule(&mut ptr, module.into_raw(), &mut err) != 0 {
-                return Err(Message::from_raw(err));
+            let module = module.into_raw();
+            if LLVMCreateExecutionEngineForModule(&mut ptr, module, &mut err) != 0 {
+                return Err(Message::from_raw(err).into());
             }
-            Ok(Owning::from_raw(ptr))
+            Ok(OwnedExecutionEngine::new(
+                Owning::from_raw(ptr),
+                module,
+                None,
+            ))
         }
     }
 
     pub fn create_interpreter_for_module(
         module: Owning<Module<'c>>,
-    ) -> Result<Owning<Self>, Message> {
+    ) -> Result<(OwnedExecutionEngine<'c>, ModuleHandle<'c>), Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
-            if LLVMCreateInterpreterForModule(&mut ptr, module.into_raw(), &mut err) != 0 {
-                return Err(Message::from_raw(err));
+            let module = module.into_raw();
+            if LLVMCreateInterpreterForModule(&mut ptr, module, &mut err) != 0 {
+                return Err(Message::from_raw(err).into());
             }
-            Ok(Owning::from_raw(ptr))
+            Ok(OwnedExecutionEngine::new(
+                Owning::from_raw(ptr),
+                module,
+                Some(true),
+            ))
         }
     }
 
     pub fn create_jit_compiler_for_module(
         module: Owning<Module<'c>>,
         opt_level: u32,
-    ) -> Result<Owning<Self>, Message> {
+    ) -> Result<(OwnedExecutionEngine<'c>, ModuleHandle<'c>), Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
-            if LLVMCreateJITCompilerForModule(&mut ptr, module.into_raw(), opt_level, &mut err) != 0
-            {
-                return Err(Message::from_raw(err));
+            let module = module.into_raw();
+            if LLVMCreateJITCompilerForModule(&mut ptr, module, opt_level, &mut err) != 0 {
+                return Err(Message::from_raw(err).into());
+            }
+            Ok(OwnedExecutionEngine::new(
+                Owning::from_raw(ptr),
+                module,
+                Some(false),
+            ))
+        }
+    }
+}
+
+/// An execution engine, with the id that tells its module handles from those
+/// of other engines and whether it is an interpreter.
+pub struct OwnedExecutionEngine<'c> {
+    engine: Owning<ExecutionEngine<'c>>,
+    id: u64,
+    interpreter: bool,
+}
+
+static NEXT_ENGINE_ID: AtomicU64 = AtomicU64::new(0);
+
+impl<'c> OwnedExecutionEngine<'c> {
+    /// `interpreter` is `None` when LLVM chose the kind of engine. Only the
+    /// compilers have a target machine.
+    fn new(
+        engine: Owning<ExecutionEngine<'c>>,
+        module: LLVMModuleRef,
+        interpreter: Option<bool>,
+    ) -> (Self, ModuleHandle<'c>) {
+        let interpreter = interpreter.unwrap_or_else(|| unsafe {
+            LLVMGetExecutionEngineTargetMachine(engine.as_raw()).is_null()
+        });
+        let this = Self {
+            engine,
+            id: NEXT_ENGINE_ID.fetch_add(1, Ordering::Relaxed),
+            interpreter,
+        };
+        let handle = this.handle(module);
+        (this, handle)
+    }
+
+    fn handle(&self, module: LLVMModuleRef) -> ModuleHandle<'c> {
+        ModuleHandle {
+            engine: self.id,
+            module,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> Owning<ExecutionEngine<'c>> {
+        self.engine
+    }
+
+    pub fn add_module(&self, m: Owning<Module<'c>>) -> ModuleHandle<'c> {
+        let module = m.into_raw();
+        unsafe { LLVMAddModule(self.as_raw(), module) };
+        self.handle(module)
+    }
+
+    /// Run `function` with the interpreter, converting `args` to generic values
+    /// and the result back by the type of the function. Fails if `function`
+    /// is a declaration or is not of type `fun<Args, Output>`, or if this engine
+    /// is not an interpreter, as MCJIT aborts on functions not like `main`.
+    pub fn run<Args: GenericTagTuple, Output: GenericReturnTypeTag>(
+        &self,
+        function: &Function<impl FunTypeTag>,
+        args: Args::Rust,
+    ) -> Result<Output::Rust, Error> {
+        let name = || String::from_utf8_lossy(function.get_name()).into_owned();
+        if !self.interpreter {
+            return Err(Error::Message(format!(
+                "run function {}, the execution engine is not an interpreter",
+                name()
+            )));
+        }
+        let function = function
+            .try_cast::<Function<fun<Args, Output>>>()
+            .ok_or_else(|| Error::Message(format!("function {} has a different type", name())))?;
+        if function.is_declaration() {
+            return Err(Error::Message(format!(
+                "function {} is a declaration",
+                name()
+            )));
+        }
+        let ty = function.get_value_type();
+        let args = Args::to_generic_values(ty.get_params(), args);
+        let args = args.iter().map(|arg| &**arg).collect::<Vec<_>>();
+        let result = self.run_function(function, &args);
+        Ok(Output::from_generic_return(ty.get_return(), &result))
+    }
+
+    /// Take back the module of `handle`. Its functions are no longer found
+    /// by [`ExecutionEngine::get_function_address`], although MCJIT keeps the
+    /// code compiled so far until the engine is dropped.
+    pub fn remove_module(&self, handle: ModuleHandle<'c>) -> Result<Owning<Module<'c>>, Error> {
+        handle.check(self)?;
+        unsafe {
+            let mut ptr = null_mut();
+            let mut err = null_mut();
+            if LLVMRemoveModule(self.as_raw(), handle.module, &mut ptr, &mut err) != 0 {
+                return Err(Message::from_raw(err).into());
             }
             Ok(Owning::from_raw(ptr))
         }
     }
 }
 
+impl<'c> Deref for OwnedExecutionEngine<'c> {
+    type Target = ExecutionEngine<'c>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.engine
+    }
+}
+
+/// A module owned by an execution engine, to take back with
+/// [`OwnedExecutionEngine::remove_module`].
+pub struct ModuleHandle<'c> {
+    engine: u64,
+    module: LLVMModuleRef,
+    _marker: PhantomData<&'c Context>,
+}
+
+impl<'c> ModuleHandle<'c> {
+    fn check(&self, engine: &OwnedExecutionEngine<'c>) -> Result<(), Error> {
+        if self.engine != engine.id {
+            return Err(Error::Message(
+                "use a module handle with another execution engine than its own".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The module, as long as `engine` owns it. Fails if `engine` is not the
+    /// engine of this handle.
+    pub fn get<'e>(&self, engine: &'e OwnedExecutionEngine<'c>) -> Result<&'e Module<'c>, Error> {
+        self.check(engine)?;
+        Ok(unsafe { Module::from_raw(self.module) })
+    }
+}
+
 pub struct MCJITCompilerOptions {
     pub opt_level: u32,
     pub code_model: LLVMCodeModel,
@@ -166,10 +471,11 @@ impl<'c> ExecutionEngine<'c> {
     pub fn create_mc_jit_compiler_for_module(
         module: Owning<Module<'c>>,
         option: MCJITCompilerOptions,
-    ) -> Result<Owning<Self>, Message> {
+    ) -> Result<(OwnedExecutionEngine<'c>, ModuleHandle<'c>), Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
+            let module = module.into_raw();
             let this = option.mc_jit_memory_manager;
             let mut o = LLVMMCJITCompilerOptions {
                 OptLevel: option.opt_level,
@@ -180,15 +486,19 @@ impl<'c> ExecutionEngine<'c> {
             };
             if LLVMCreateMCJITCompilerForModule(
                 &mut ptr,
-                module.into_raw(),
+                module,
                 &mut o,
                 size_of::<LLVMMCJITCompilerOptions>(),
                 &mut err,
             ) != 0
             {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
-            Ok(Owning::from_raw(ptr))
+            Ok(OwnedExecutionEngine::new(
+                Owning::from_raw(ptr),
+                module,
+                Some(false),
+            ))
         }
     }
 }
@@ -250,21 +560,6 @@ impl<'c> ExecutionEngine<'c> {
         unsafe { LLVMFreeMachineCodeForFunction(self.as_raw(), f.as_raw()) };
     }
 
-    pub fn add_module(&self, m: Owning<Module<'c>>) {
-        unsafe { LLVMAddModule(self.as_raw(), m.into_raw()) };
-    }
-
-    pub fn remove_module(&self, m: *const Module<'c>) -> Result<Owning<Module>, Message> {
-        unsafe {
-            let mut ptr = null_mut();
-            let mut err = null_mut();
-            if LLVMRemoveModule(self.as_raw(), m as _, &mut ptr, &mut err) != 0 {
-                return Err(Message::from_raw(err));
-            }
-            Ok(Owning::from_raw(ptr))
-        }
-    }
-
     pub fn find_function(&self, name: &CStr) -> Option<&'c Value<fun_any>> {
         unsafe {
             let mut ptr = null_mut();
@@ -301,15 +596,21 @@ impl<'c> ExecutionEngine<'c> {
         unsafe { LLVMGetGlobalValueAddress(self.as_raw(), name.as_ptr()) }
     }
 
+    /// The address of the function `name` defines in one of the modules of
+    /// the engine, compiling it if needed, or 0 if there is none.
     pub fn get_function_address(&self, name: &CStr) -> u64 {
+        // MCJIT still finds the code of removed modules.
+        if self.find_function(name).is_none() {
+            return 0;
+        }
         unsafe { LLVMGetFunctionAddress(self.as_raw(), name.as_ptr()) }
     }
 
-    pub fn get_err_msg(&self) -> Result<(), Message> {
+    pub fn get_err_msg(&self) -> Result<(), Error> {
         unsafe {
             let mut ptr = null_mut();
             if LLVMExecutionEngineGetErrMsg(self.as_raw(), &mut ptr) != 0 {
-                return Err(Message::from_raw(ptr));
+                return Err(Message::from_raw(ptr).into());
             }
             Ok(())
         }
@@ -334,7 +635,7 @@ pub trait SimpleMCJitMemoryManager {
         is_read_only: bool,
     ) -> *mut u8;
 
-    fn finalize_memory(&self) -> Result<(), Message>;
+    fn finalize_memory(&self) -> Result<(), Error>;
 }
 
 impl MCJITMemoryManager {
@@ -348,14 +649,16 @@ impl MCJITMemoryManager {
             section_id: u32,
             section_name: *const c_char,
         ) -> *mut u8 {
-            unsafe {
+            // RuntimeDyld reports a failed allocation as a fatal error, so a
+            // panic cannot be resumed.
+            catch_or_abort(|| unsafe {
                 (*(this as *const T)).allocate_code_section(
                     size,
                     alignment,
                     section_id,
                     CStr::from_ptr(section_name),
                 )
-            }
+            })
         }
         extern "C" fn allocate_data_section_raw<T: SimpleMCJitMemoryManager>(
             this: *mut c_void,
@@ -365,7 +668,7 @@ impl MCJITMemoryManager {
             section_name: *const c_char,
             is_read_only: LLVMBool,
         ) -> *mut u8 {
-            unsafe {
+            catch_or_abort(|| unsafe {
                 (*(this as *const T)).allocate_data_section(
                     size,
                     alignment,
@@ -373,25 +676,24 @@ impl MCJITMemoryManager {
                     CStr::from_ptr(section_name),
                     is_read_only != 0,
                 )
-            }
+            })
         }
         extern "C" fn finalize_memory_raw<T: SimpleMCJitMemoryManager>(
             this: *mut c_void,
             err_msg: *mut *mut c_char,
         ) -> LLVMBool {
-            unsafe {
-                if let Err(e) = (*(this as *const T)).finalize_memory() {
-                    *err_msg = e.into_raw();
-                    1
-                } else {
-                    0
-                }
+            // MCJIT ignores the errors of finalization, so a panic turned
+            // into one would go unnoticed.
+            let result = catch_or_abort(|| unsafe { (*(this as *const T)).finalize_memory() });
+            if let Err(e) = result {
+                unsafe { *err_msg = e.into_message().into_raw() };
+                1
+            } else {
+                0
             }
         }
         extern "C" fn destroy_raw<T: SimpleMCJitMemoryManager>(this: *mut c_void) {
-            unsafe {
-                let _ = Box::from_raw(this as *mut T);
-            }
+            catch_or_abort(|| unsafe { drop(Box::from_raw(this as *mut T)) })
         }
 
         unsafe {
@@ -430,6 +732,15 @@ impl OpaqueDrop for LLVMOpaqueMCJITMemoryManager {
     }
 }
 
+/// The listeners built into LLVM, for [`ExecutionEngine`] and
+/// [`OrcObjectLayer::orc_rt_dyld_object_linking_layer_register_jit_event_listener`].
+///
+/// The C API cannot create a listener with custom callbacks, so listeners
+/// implemented in Rust are not supported. To name JIT-compiled code for a
+/// profiler, feed the writers of [`crate::perf`] with the addresses of the
+/// symbols looked up instead.
+///
+/// [`OrcObjectLayer::orc_rt_dyld_object_linking_layer_register_jit_event_listener`]: crate::orc2::OrcObjectLayer::orc_rt_dyld_object_linking_layer_register_jit_event_listener
 impl JITEventListener {
     pub fn create_gdb_registration_listener() -> &'static JITEventListener {
         unsafe { JITEventListener::from_raw(LLVMCreateGDBRegistrationListener()) }
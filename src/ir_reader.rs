@@ -7,12 +7,12 @@ use crate::owning::Owning;
 use crate::*;
 
 impl Context {
-    pub fn parse_ir(&self, mem_buf: &MemoryBuffer) -> Result<Owning<Module>, Message> {
+    pub fn parse_ir(&self, mem_buf: &MemoryBuffer) -> Result<Owning<Module>, Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
             if LLVMParseIRInContext(self.as_raw(), mem_buf.as_raw(), &mut ptr, &mut err) != 0 {
-                Err(Message::from_raw(err))
+                Err(Message::from_raw(err).into())
             } else {
                 Ok(Owning::from_raw(ptr))
             }
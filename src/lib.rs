@@ -14,8 +14,10 @@ pub mod analysis;
 pub mod bit_reader;
 pub mod bit_writer;
 pub mod blake3;
+mod callback;
 pub mod comdat;
 pub mod core;
+pub mod data_layout;
 pub mod debuginfo;
 pub mod disassembler;
 pub mod error;
@@ -24,10 +26,14 @@ pub mod execution_engine;
 pub mod ir_reader;
 pub mod linker;
 pub mod lto;
+#[cfg(unix)]
+pub mod memory_manager;
 pub mod object;
 pub mod opaque;
 pub mod orc2;
 pub mod owning;
+#[cfg(target_os = "linux")]
+pub mod perf;
 pub mod remark;
 pub mod support;
 pub mod target;
@@ -35,6 +41,8 @@ pub mod target_machine;
 pub mod transforms;
 pub mod type_tag;
 
+pub use crate::error::Error;
+
 #[repr(transparent)]
 pub struct MemoryBuffer {
     _opaque: PhantomOpaque,
@@ -1,16 +1,33 @@
 use llvm_sys::linker::LLVMLinkModules2;
 
+use crate::data_layout::DataLayout;
 use crate::owning::Owning;
 use crate::*;
 
 impl<'c> Module<'c> {
-    pub fn link_modules(&self, src: Owning<Self>) -> Result<(), ()> {
+    pub fn link_modules(&self, src: Owning<Self>) -> Result<(), Error> {
         unsafe {
             if LLVMLinkModules2(self.as_raw(), src.into_raw()) != 0 {
-                Err(())
+                Err(Error::Failed(format!(
+                    "link into module {:?}, see the diagnostic handler of its context",
+                    String::from_utf8_lossy(self.get_identifier())
+                )))
             } else {
                 Ok(())
             }
         }
     }
+
+    /// Link `src` into this module, once its data layout is checked to be
+    /// compatible with the one of this module, see
+    /// [`DataLayout::check_compatible`]. LLVM itself only warns about it.
+    pub fn link_modules_checked(&self, src: Owning<Self>) -> Result<(), Error> {
+        let layout = |m: &Self| {
+            m.get_data_layout_str()
+                .to_string_lossy()
+                .parse::<DataLayout>()
+        };
+        layout(self)?.check_compatible(&layout(&src)?)?;
+        self.link_modules(src)
+    }
 }
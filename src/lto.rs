@@ -7,9 +7,13 @@ use crate::owning::{OpaqueDrop, Owning};
 use crate::*;
 
 #[inline(always)]
-unsafe fn check_error(v: bool) -> Result<(), CString> {
+unsafe fn check_error(v: bool) -> Result<(), Error> {
     if v {
-        Err(unsafe { get_error_message() })
+        Err(Error::Lto(
+            unsafe { get_error_message() }
+                .to_string_lossy()
+                .into_owned(),
+        ))
     } else {
         Ok(())
     }
@@ -182,8 +186,8 @@ impl LTOModule {
     }
 
     #[inline(always)]
-    pub unsafe fn set_target_triple(&self, triple: &CStr) {
-        unsafe { lto_module_set_target_triple(self.as_raw(), triple.as_ptr()) }
+    pub unsafe fn set_target_triple(&self, triple: impl AsRef<CStr>) {
+        unsafe { lto_module_set_target_triple(self.as_raw(), triple.as_ref().as_ptr()) }
     }
 
     #[inline(always)]
@@ -209,7 +213,7 @@ impl LTOModule {
     }
 
     #[inline(always)]
-    pub fn get_macho_cputype(&self) -> Result<(u32, u32), CString> {
+    pub fn get_macho_cputype(&self) -> Result<(u32, u32), Error> {
         unsafe {
             let mut cputype = 0;
             let mut cpusubtype = 0;
@@ -252,7 +256,7 @@ impl OpaqueDrop for LLVMOpaqueLTOCodeGenerator {
 
 impl LTOCodeGenerator {
     #[inline(always)]
-    pub fn add_module(&self, module: &LTOModule) -> Result<(), CString> {
+    pub fn add_module(&self, module: &LTOModule) -> Result<(), Error> {
         unsafe { check_error(lto_codegen_add_module(self.as_raw(), module.as_raw()) != 0) }
     }
 
@@ -262,12 +266,12 @@ impl LTOCodeGenerator {
     }
 
     #[inline(always)]
-    pub fn set_debug_model(&self, model: lto_debug_model) -> Result<(), CString> {
+    pub fn set_debug_model(&self, model: lto_debug_model) -> Result<(), Error> {
         unsafe { check_error(lto_codegen_set_debug_model(self.as_raw(), model) != 0) }
     }
 
     #[inline(always)]
-    pub fn set_pic_model(&self, model: lto_codegen_model) -> Result<(), CString> {
+    pub fn set_pic_model(&self, model: lto_codegen_model) -> Result<(), Error> {
         unsafe { check_error(lto_codegen_set_pic_model(self.as_raw(), model) != 0) }
     }
 
@@ -297,12 +301,12 @@ impl LTOCodeGenerator {
     }
 
     #[inline(always)]
-    pub fn write_merged_modules(&self, path: &CStr) -> Result<(), CString> {
+    pub fn write_merged_modules(&self, path: &CStr) -> Result<(), Error> {
         unsafe { check_error(lto_codegen_write_merged_modules(self.as_raw(), path.as_ptr()) != 0) }
     }
 
     #[inline(always)]
-    pub fn compile(&self) -> Result<&[u8], CString> {
+    pub fn compile(&self) -> Result<&[u8], Error> {
         unsafe {
             let mut len = 0;
             let ptr = lto_codegen_compile(self.as_raw(), &mut len);
@@ -312,7 +316,7 @@ impl LTOCodeGenerator {
     }
 
     #[inline(always)]
-    pub fn compile_to_file(&self) -> Result<CString, CString> {
+    pub fn compile_to_file(&self) -> Result<CString, Error> {
         unsafe {
             let mut name = null();
             check_error(lto_codegen_compile_to_file(self.as_raw(), &mut name) != 0)?;
@@ -321,12 +325,12 @@ impl LTOCodeGenerator {
     }
 
     #[inline(always)]
-    pub fn optimize(&self) -> Result<(), CString> {
+    pub fn optimize(&self) -> Result<(), Error> {
         unsafe { check_error(lto_codegen_optimize(self.as_raw()) != 0) }
     }
 
     #[inline(always)]
-    pub fn compile_optimized(&self) -> Result<&[u8], CString> {
+    pub fn compile_optimized(&self) -> Result<&[u8], Error> {
         unsafe {
             let mut len = 0;
             let ptr = lto_codegen_compile_optimized(self.as_raw(), &mut len);
@@ -443,7 +447,7 @@ impl ThinLTOCodeGenerator {
     }
 
     #[inline(always)]
-    pub fn set_pic_model(&self, model: lto_codegen_model) -> Result<(), CString> {
+    pub fn set_pic_model(&self, model: lto_codegen_model) -> Result<(), Error> {
         unsafe { check_error(thinlto_codegen_set_pic_model(self.as_raw(), model) != 0) }
     }
 
@@ -0,0 +1,355 @@
+//! A memory manager for MCJIT allocating sections with `mmap`.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Error;
+use crate::execution_engine::SimpleMCJitMemoryManager;
+
+/// The minimum size of a block of memory sections are allocated from.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    /// Readable and executable once finalized.
+    Code,
+    /// Readable once finalized.
+    ReadOnlyData,
+    /// Readable and writable.
+    Data,
+}
+
+/// A section allocated by a [`MmapMemoryManager`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionRange {
+    pub name: CString,
+    pub section_id: u32,
+    pub kind: SectionKind,
+    pub address: usize,
+    pub size: usize,
+}
+
+impl SectionRange {
+    pub fn contains(&self, address: usize) -> bool {
+        (self.address..self.address + self.size).contains(&address)
+    }
+}
+
+/// An MCJIT memory manager allocating sections from blocks of memory mapped
+/// with `mmap`, and protecting code as readable and executable and read-only
+/// data as readable in `finalize_memory`.
+///
+/// Clones share their memory, which is unmapped with the last one, so keep a
+/// clone to inspect the sections after handing the manager to MCJIT.
+#[derive(Clone, Default)]
+pub struct MmapMemoryManager {
+    state: Arc<Mutex<MmapState>>,
+}
+
+#[derive(Default)]
+struct MmapState {
+    blocks: Vec<Block>,
+    sections: Vec<SectionRange>,
+}
+
+// The blocks are only accessed under the lock.
+unsafe impl Send for MmapState {}
+
+struct Block {
+    ptr: *mut u8,
+    len: usize,
+    used: usize,
+    kind: SectionKind,
+    /// Finalized blocks are protected, so no more sections go into them.
+    finalized: bool,
+}
+
+impl Drop for MmapState {
+    fn drop(&mut self) {
+        for block in &self.blocks {
+            unsafe { libc::munmap(block.ptr as _, block.len) };
+        }
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+impl MmapState {
+    fn allocate(
+        &mut self,
+        kind: SectionKind,
+        size: usize,
+        alignment: u32,
+        section_id: u32,
+        section_name: &CStr,
+    ) -> *mut u8 {
+        let alignment = (alignment as usize).max(1);
+        debug_assert!(alignment.is_power_of_two());
+        let fits = |block: &Block| {
+            let start = (block.ptr as usize + block.used).next_multiple_of(alignment);
+            !block.finalized && block.kind == kind && start + size <= block.ptr as usize + block.len
+        };
+        let block = match self.blocks.iter().position(fits) {
+            Some(block) => block,
+            None => {
+                let len = (size + alignment)
+                    .max(BLOCK_SIZE)
+                    .next_multiple_of(page_size());
+                let ptr = unsafe {
+                    libc::mmap(
+                        null_mut(),
+                        len,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                        -1,
+                        0,
+                    )
+                };
+                if ptr == libc::MAP_FAILED {
+                    return null_mut();
+                }
+                self.blocks.push(Block {
+                    ptr: ptr as _,
+                    len,
+                    used: 0,
+                    kind,
+                    finalized: false,
+                });
+                self.blocks.len() - 1
+            }
+        };
+        let block = &mut self.blocks[block];
+        let address = (block.ptr as usize + block.used).next_multiple_of(alignment);
+        block.used = address + size - block.ptr as usize;
+        self.sections.push(SectionRange {
+            name: section_name.to_owned(),
+            section_id,
+            kind,
+            address,
+            size,
+        });
+        address as *mut u8
+    }
+
+    fn finalize(&mut self) -> Result<(), Error> {
+        for block in self.blocks.iter_mut().filter(|block| !block.finalized) {
+            let prot = match block.kind {
+                SectionKind::Code => libc::PROT_READ | libc::PROT_EXEC,
+                SectionKind::ReadOnlyData => libc::PROT_READ,
+                SectionKind::Data => continue,
+            };
+            if unsafe { libc::mprotect(block.ptr as _, block.len, prot) } != 0 {
+                return Err(Error::Message(format!(
+                    "mprotect failed: {}",
+                    io::Error::last_os_error()
+                )));
+            }
+            if block.kind == SectionKind::Code {
+                invalidate_instruction_cache(block.ptr, block.len)?;
+            }
+            block.finalized = true;
+        }
+        Ok(())
+    }
+}
+
+/// The instruction cache of x86 is coherent with the data cache.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn invalidate_instruction_cache(_ptr: *mut u8, _len: usize) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(all(
+    target_vendor = "apple",
+    not(any(target_arch = "x86", target_arch = "x86_64"))
+))]
+fn invalidate_instruction_cache(ptr: *mut u8, len: usize) -> Result<(), Error> {
+    extern "C" {
+        fn sys_icache_invalidate(start: *mut libc::c_void, len: libc::size_t);
+    }
+    unsafe { sys_icache_invalidate(ptr as _, len) };
+    Ok(())
+}
+
+/// `__clear_cache` is provided by libgcc and compiler-rt on these systems.
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    not(any(target_arch = "x86", target_arch = "x86_64"))
+))]
+fn invalidate_instruction_cache(ptr: *mut u8, len: usize) -> Result<(), Error> {
+    extern "C" {
+        fn __clear_cache(begin: *mut libc::c_char, end: *mut libc::c_char);
+    }
+    unsafe { __clear_cache(ptr as _, ptr.add(len) as _) };
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_vendor = "apple",
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn invalidate_instruction_cache(_ptr: *mut u8, _len: usize) -> Result<(), Error> {
+    Err(Error::Message(
+        "invalidate the instruction cache, not supported on this target".into(),
+    ))
+}
+
+impl MmapMemoryManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sections allocated so far, in allocation order.
+    pub fn sections(&self) -> Vec<SectionRange> {
+        self.state.lock().unwrap().sections.clone()
+    }
+
+    /// The section containing `address`, if any.
+    pub fn find_section(&self, address: usize) -> Option<SectionRange> {
+        let state = self.state.lock().unwrap();
+        state
+            .sections
+            .iter()
+            .find(|section| section.contains(address))
+            .cloned()
+    }
+}
+
+impl SimpleMCJitMemoryManager for MmapMemoryManager {
+    fn allocate_code_section(
+        &self,
+        size: usize,
+        alignment: u32,
+        section_id: u32,
+        section_name: &CStr,
+    ) -> *mut u8 {
+        self.state.lock().unwrap().allocate(
+            SectionKind::Code,
+            size,
+            alignment,
+            section_id,
+            section_name,
+        )
+    }
+
+    fn allocate_data_section(
+        &self,
+        size: usize,
+        alignment: u32,
+        section_id: u32,
+        section_name: &CStr,
+        is_read_only: bool,
+    ) -> *mut u8 {
+        let kind = if is_read_only {
+            SectionKind::ReadOnlyData
+        } else {
+            SectionKind::Data
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .allocate(kind, size, alignment, section_id, section_name)
+    }
+
+    fn finalize_memory(&self) -> Result<(), Error> {
+        self.state.lock().unwrap().finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocate(state: &mut MmapState, kind: SectionKind, size: usize, alignment: u32) -> usize {
+        let ptr = state.allocate(kind, size, alignment, 0, c"section");
+        assert!(!ptr.is_null());
+        ptr as usize
+    }
+
+    fn in_block(block: &Block, address: usize) -> bool {
+        (block.ptr as usize..block.ptr as usize + block.len).contains(&address)
+    }
+
+    #[test]
+    fn sections_are_aligned() {
+        let mut state = MmapState::default();
+        for alignment in [0, 1, 2, 8, 16, 64, 4096] {
+            let address = allocate(&mut state, SectionKind::Data, 3, alignment);
+            assert_eq!(address % (alignment as usize).max(1), 0);
+        }
+        // All of them fit in one block.
+        assert_eq!(state.blocks.len(), 1);
+    }
+
+    #[test]
+    fn blocks_are_whole_pages() {
+        let mut state = MmapState::default();
+        allocate(&mut state, SectionKind::Code, 1, 16);
+        let size = 3 * BLOCK_SIZE + 1;
+        let large = allocate(&mut state, SectionKind::Code, size, 4096);
+        assert_eq!(state.blocks.len(), 2);
+        for block in &state.blocks {
+            assert_eq!(block.ptr as usize % page_size(), 0);
+            assert_eq!(block.len % page_size(), 0);
+            assert!(block.len >= BLOCK_SIZE);
+        }
+        let block = &state.blocks[1];
+        assert!(large + size <= block.ptr as usize + block.len);
+    }
+
+    #[test]
+    fn sections_of_different_kinds_do_not_share_blocks() {
+        let mut state = MmapState::default();
+        allocate(&mut state, SectionKind::Code, 16, 16);
+        allocate(&mut state, SectionKind::ReadOnlyData, 16, 16);
+        allocate(&mut state, SectionKind::Data, 16, 16);
+        allocate(&mut state, SectionKind::Code, 16, 16);
+        let kinds = state
+            .blocks
+            .iter()
+            .map(|block| block.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                SectionKind::Code,
+                SectionKind::ReadOnlyData,
+                SectionKind::Data
+            ]
+        );
+        assert_eq!(state.sections.len(), 4);
+    }
+
+    #[test]
+    fn finalized_blocks_are_not_reused() {
+        let mut state = MmapState::default();
+        let code = allocate(&mut state, SectionKind::Code, 16, 16);
+        let data = allocate(&mut state, SectionKind::Data, 16, 16);
+        state.finalize().unwrap();
+        // Data stays writable.
+        unsafe { (data as *mut u8).write(1) };
+        let next = allocate(&mut state, SectionKind::Code, 16, 16);
+        assert_eq!(state.blocks.len(), 3);
+        assert!(!in_block(&state.blocks[0], next));
+        assert!(in_block(&state.blocks[0], code));
+        // The data block is never protected, so it is still filled.
+        let more = allocate(&mut state, SectionKind::Data, 16, 16);
+        assert!(in_block(&state.blocks[1], more));
+    }
+}
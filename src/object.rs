@@ -49,12 +49,12 @@ unsafe impl<'m> Opaque for Binary<'m> {
 }
 
 impl<'m> Binary<'m> {
-    pub fn create(mem_buf: &'m MemoryBuffer, context: &Context) -> Result<Owning<Self>, Message> {
+    pub fn create(mem_buf: &'m MemoryBuffer, context: &Context) -> Result<Owning<Self>, Error> {
         unsafe {
             let mut error = null_mut();
             let ptr = LLVMCreateBinary(mem_buf.as_raw(), context.as_raw(), &mut error);
             if ptr.is_null() {
-                Err(Message::from_raw(error))
+                Err(Message::from_raw(error).into())
             } else {
                 Ok(Owning::from_raw(ptr))
             }
@@ -80,7 +80,7 @@ impl<'m> Binary<'m> {
     pub fn mach_o_universal_binary_copy_object_for_arch(
         &self,
         arch: &[u8],
-    ) -> Result<Owning<Binary<'m>>, Message> {
+    ) -> Result<Owning<Binary<'m>>, Error> {
         unsafe {
             let mut error = null_mut();
             let ptr = LLVMMachOUniversalBinaryCopyObjectForArch(
@@ -90,7 +90,7 @@ impl<'m> Binary<'m> {
                 &mut error,
             );
             if ptr.is_null() {
-                Err(Message::from_raw(error))
+                Err(Message::from_raw(error).into())
             } else {
                 Ok(Owning::from_raw(ptr))
             }
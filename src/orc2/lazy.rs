@@ -0,0 +1,456 @@
+use std::ffi::{CStr, CString};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use llvm_sys::comdat::LLVMSetComdat;
+use llvm_sys::core::*;
+use llvm_sys::orc2::*;
+use llvm_sys::prelude::{LLVMModuleRef, LLVMValueRef};
+use llvm_sys::{LLVMLinkage, LLVMTypeKind, LLVMVisibility};
+
+use crate::error::Error;
+use crate::orc2::lljit::{OrcLLJIT, OrcLLJITBuilder, OrcLLJITHandle};
+use crate::orc2::*;
+use crate::owning::Owning;
+
+type CompileHook = Box<dyn Fn(&CStr, &Module) + Send + Sync>;
+type ErrorReporter = Arc<RwLock<Option<Box<dyn Fn(Error) + Send + Sync>>>>;
+
+/// The JITDylib defining the functions of lazily added modules.
+const IMPLEMENTATION: &CStr = c"main.<lazy>";
+
+/// A JIT compiling each function of a lazily added module the first time it is
+/// called, like LLVM's `LLLazyJIT`.
+///
+/// [`OrcLazyJIT::add_lazy_llvm_ir_module`] splits a module into a module per
+/// function and a module of its global variables, like the per-function
+/// partitioning of LLVM's `CompileOnDemandLayer`. The main JITDylib exports the
+/// functions as stubs and defines the global variables. The functions of all
+/// lazily added modules are defined in one more JITDylib, under names of their
+/// own, so that they find each other through the main JITDylib and every call
+/// to a function not compiled yet goes through its stub.
+///
+/// When a function fails to compile, the error is reported to the error reporter
+/// set by [`OrcLazyJIT::set_error_reporter`], and the process aborts, as its
+/// call cannot return.
+pub struct OrcLazyJIT {
+    lctm: Owning<OrcLazyCallThroughManager>,
+    ism: Owning<OrcIndirectStubsManager>,
+    compile_hook: Arc<RwLock<Option<CompileHook>>>,
+    reporter: ErrorReporter,
+    next_module: AtomicUsize,
+    jit: Owning<OrcLLJIT>,
+}
+
+fn report(reporter: &ErrorReporter, e: Error) {
+    match &*reporter.read().unwrap() {
+        Some(report) => report(e),
+        // The default reporter of LLVM.
+        None => eprintln!("JIT session error: {e}"),
+    }
+}
+
+/// Called instead of a function that failed to compile, once the error has been
+/// reported.
+extern "C" fn lazy_compile_failed() {
+    std::process::abort()
+}
+
+impl OrcLazyJIT {
+    pub fn create(builder: Option<Owning<OrcLLJITBuilder>>) -> Result<Self, Error> {
+        let jit = OrcLLJIT::create(builder)?;
+        let triple = jit.get_triple();
+        let ism = OrcIndirectStubsManager::create_local_indirect_stubs_manager(triple);
+        let es = jit.get_execution_session();
+        let lctm = OrcLazyCallThroughManager::create_local(
+            triple,
+            es,
+            lazy_compile_failed as *const () as usize as _,
+        )?;
+        let reporter = ErrorReporter::default();
+        let shared = reporter.clone();
+        es.set_error_reporter(move |e| report(&shared, e));
+
+        let implementation = es.create_jit_dylib(IMPLEMENTATION)?;
+        implementation.set_link_order(
+            es,
+            &[(
+                jit.get_main_jit_dylib(),
+                LLVMOrcJITDylibLookupFlags::LLVMOrcJITDylibLookupFlagsMatchAllSymbols,
+            )],
+        );
+        let process =
+            OrcDefinitionGenerator::create_dynamic_library_search_generator_for_process_raw(
+                jit.get_global_prefix() as u8,
+                None,
+                null_mut(),
+            )?;
+        unsafe { LLVMOrcJITDylibAddGenerator(implementation.as_raw(), process.into_raw()) };
+        Ok(Self {
+            lctm,
+            ism,
+            compile_hook: Arc::new(RwLock::new(None)),
+            reporter,
+            next_module: AtomicUsize::new(0),
+            jit,
+        })
+    }
+
+    /// Set the function reporting the errors no caller can receive, such as
+    /// the failure to compile a lazily called function. It replaces the error
+    /// reporter of the execution session, which must not be set otherwise.
+    pub fn set_error_reporter(&self, f: impl Fn(Error) + Send + Sync + 'static) {
+        *self.reporter.write().unwrap() = Some(Box::new(f));
+    }
+
+    /// Set a hook called with the name of every function of a lazily added
+    /// module, and the module compiling it, right before it is compiled.
+    pub fn set_compile_hook(&self, hook: impl Fn(&CStr, &Module) + Send + Sync + 'static) {
+        *self.compile_hook.write().unwrap() = Some(Box::new(hook));
+    }
+
+    /// Define host symbols, visible both to lookups and to lazily added code.
+    pub fn define_symbols<'a>(
+        &self,
+        symbols: impl IntoIterator<Item = (&'a str, SymbolAddr)>,
+    ) -> Result<(), Error> {
+        self.get_main_jit_dylib().define_symbols(&self.jit, symbols)
+    }
+
+    /// Add a module of `context` whose functions are compiled on their first
+    /// call.
+    ///
+    /// Local definitions are renamed and hidden, so that the functions can
+    /// reference them from their own modules. Modules with aliases or ifuncs
+    /// are not split, and are compiled as a whole the first time one of their
+    /// symbols is looked up. Static constructors of the module are not run.
+    pub fn add_lazy_llvm_ir_module<'c>(
+        &self,
+        context: &'c OrcThreadSafeContext,
+        tsm: Owning<OrcThreadSafeModule<'c>>,
+    ) -> Result<(), Error> {
+        let id = self.next_module.fetch_add(1, Ordering::Relaxed);
+        // Only the address of the context is used.
+        let expected = unsafe { context.get_context() } as *const Context;
+        let partitions = tsm.with_module_do(|m| {
+            if !std::ptr::eq(m.context(), expected) {
+                return Err(Error::Message(
+                    "add a lazy module, it does not belong to the given context".into(),
+                ));
+            }
+            Ok(unsafe { partition(m.as_raw(), id) })
+        })?;
+        drop(tsm);
+        let partitions = partitions
+            .into_iter()
+            .map(|partition| {
+                let tsm = context.create_module(unsafe { Owning::from_raw(partition.module) });
+                (tsm, partition.symbols, partition.export)
+            })
+            .collect::<Vec<_>>();
+
+        let main = self.get_main_jit_dylib();
+        let implementation = self
+            .get_execution_session()
+            .get_jit_dylib_by_name(IMPLEMENTATION)
+            .ok_or_else(|| {
+                Error::Failed(format!("add a lazy module, {IMPLEMENTATION:?} is missing"))
+            })?;
+        let mut aliases = Vec::new();
+        for (tsm, definitions, export) in partitions {
+            let mangled = definitions
+                .iter()
+                .map(|(name, flags)| (self.mangle_and_intern(name), *flags))
+                .collect::<Vec<_>>();
+            let mut symbols = SymbolFlagsMap::new();
+            for (name, flags) in &mangled {
+                symbols.insert(name, *flags);
+            }
+            let functions = match &export {
+                Some(export) => vec![export.clone()],
+                None => definitions
+                    .iter()
+                    .filter(|(_, flags)| flags.contains(JitSymbolFlags::CALLABLE))
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+            };
+            let unit = LazyModule {
+                tsm,
+                jit: self.handle(),
+                compile_hook: self.compile_hook.clone(),
+                reporter: self.reporter.clone(),
+                functions,
+            };
+            // The unit is defined in a JITDylib of the JIT it borrows, and its
+            // module keeps the context alive on the C++ side.
+            let unit =
+                unsafe { OrcMaterializationUnit::create_custom_unchecked(c"lazy", symbols, unit) };
+            match export {
+                None => main.define(unit)?,
+                Some(export) => {
+                    implementation.define(unit)?;
+                    let (name, flags) = mangled.into_iter().next().unwrap();
+                    aliases.push((self.mangle_and_intern(&export), name, flags));
+                }
+            }
+        }
+        if !aliases.is_empty() {
+            let aliases = aliases
+                .iter()
+                .map(|(alias, name, flags)| (&**alias, &**name, *flags))
+                .collect::<Vec<_>>();
+            main.define(
+                self.lctm
+                    .lazy_reexports(&self.ism, implementation, &aliases),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for OrcLazyJIT {
+    type Target = OrcLLJIT;
+
+    fn deref(&self) -> &Self::Target {
+        &self.jit
+    }
+}
+
+/// A module split off a lazily added one, with the definitions it exports.
+struct Partition {
+    module: LLVMModuleRef,
+    symbols: Vec<(CString, JitSymbolFlags)>,
+    /// For a module defining a single function, the name of the function. Its
+    /// definition is renamed, so that the other functions call it through the
+    /// stub exported under this name.
+    export: Option<CString>,
+}
+
+/// The functions and global variables of a module.
+unsafe fn global_objects(m: LLVMModuleRef) -> Vec<LLVMValueRef> {
+    let mut objects = Vec::new();
+    unsafe {
+        let mut f = LLVMGetFirstFunction(m);
+        while !f.is_null() {
+            objects.push(f);
+            f = LLVMGetNextFunction(f);
+        }
+        let mut gv = LLVMGetFirstGlobal(m);
+        while !gv.is_null() {
+            objects.push(gv);
+            gv = LLVMGetNextGlobal(gv);
+        }
+    }
+    objects
+}
+
+unsafe fn value_name(gv: LLVMValueRef) -> Vec<u8> {
+    unsafe {
+        let mut len = 0;
+        let name = LLVMGetValueName2(gv, &mut len);
+        std::slice::from_raw_parts(name.cast(), len).to_vec()
+    }
+}
+
+/// Split `m`, like [`OrcLazyJIT::add_lazy_llvm_ir_module`] does. The modules
+/// are created in the context of `m`.
+unsafe fn partition(m: LLVMModuleRef, id: usize) -> Vec<Partition> {
+    unsafe {
+        if !LLVMGetFirstGlobalAlias(m).is_null() || !LLVMGetFirstGlobalIFunc(m).is_null() {
+            return vec![Partition {
+                module: LLVMCloneModule(m),
+                symbols: module_definitions(m),
+                export: None,
+            }];
+        }
+        promote_locals(m, id);
+
+        let mut partitions = Vec::new();
+        let mut f = LLVMGetFirstFunction(m);
+        while !f.is_null() {
+            if let Some((export, flags)) = definition_flags(f) {
+                let name = value_name(f);
+                let module = LLVMCloneModule(m);
+                for gv in global_objects(module) {
+                    if !LLVMIsAFunction(gv).is_null() && value_name(gv) == name {
+                        let implementation = [b"__orc_impl.", &*name].concat();
+                        LLVMSetValueName2(gv, implementation.as_ptr().cast(), implementation.len());
+                    } else {
+                        make_declaration(module, gv);
+                    }
+                }
+                let implementation = CString::new([b"__orc_impl.", export.to_bytes()].concat())
+                    .expect("the name of a definition has no nul");
+                partitions.push(Partition {
+                    module,
+                    symbols: vec![(implementation, flags)],
+                    export: Some(export),
+                });
+            }
+            f = LLVMGetNextFunction(f);
+        }
+
+        let module = LLVMCloneModule(m);
+        for gv in global_objects(module) {
+            if !LLVMIsAFunction(gv).is_null() {
+                make_declaration(module, gv);
+            }
+        }
+        let symbols = module_definitions(module);
+        if symbols.is_empty() {
+            LLVMDisposeModule(module);
+        } else {
+            partitions.push(Partition {
+                module,
+                symbols,
+                export: None,
+            });
+        }
+        partitions
+    }
+}
+
+/// Give the local definitions of `m` unique hidden names, so that the modules
+/// split off it can reference them.
+unsafe fn promote_locals(m: LLVMModuleRef, id: usize) {
+    use LLVMLinkage::*;
+    unsafe {
+        for (n, gv) in global_objects(m).into_iter().enumerate() {
+            let name = value_name(gv);
+            if LLVMIsDeclaration(gv) != 0
+                || !matches!(LLVMGetLinkage(gv), LLVMInternalLinkage | LLVMPrivateLinkage)
+                || name.starts_with(b"llvm.")
+            {
+                continue;
+            }
+            let name = format!("__orc_lcl.{}.{id}.{n}", String::from_utf8_lossy(&name));
+            LLVMSetValueName2(gv, name.as_ptr().cast(), name.len());
+            LLVMSetLinkage(gv, LLVMExternalLinkage);
+            LLVMSetVisibility(gv, LLVMVisibility::LLVMHiddenVisibility);
+        }
+    }
+}
+
+/// Turn the definition `gv` of `m` into a declaration, unless it is only
+/// available for inlining. Appending globals, such as `llvm.global_ctors`, are
+/// removed instead.
+unsafe fn make_declaration(m: LLVMModuleRef, gv: LLVMValueRef) {
+    use LLVMLinkage::*;
+    unsafe {
+        let linkage = LLVMGetLinkage(gv);
+        if LLVMIsDeclaration(gv) != 0 || linkage == LLVMAvailableExternallyLinkage {
+            return;
+        }
+        if LLVMIsAFunction(gv).is_null() {
+            if linkage == LLVMAppendingLinkage {
+                LLVMDeleteGlobal(gv);
+            } else {
+                LLVMSetInitializer(gv, null_mut());
+                LLVMSetLinkage(gv, LLVMExternalLinkage);
+                LLVMSetComdat(gv, null_mut());
+            }
+            return;
+        }
+        // A function only loses its body by being deleted.
+        let declaration = LLVMAddFunction(m, c"".as_ptr(), LLVMGlobalGetValueType(gv));
+        LLVMSetFunctionCallConv(declaration, LLVMGetFunctionCallConv(gv));
+        LLVMSetVisibility(declaration, LLVMGetVisibility(gv));
+        LLVMReplaceAllUsesWith(gv, declaration);
+        let name = value_name(gv);
+        LLVMSetValueName2(gv, c"".as_ptr(), 0);
+        LLVMSetValueName2(declaration, name.as_ptr().cast(), name.len());
+        LLVMDeleteFunction(gv);
+    }
+}
+
+/// The external definitions of a module, with the flags the IR layer gives them.
+pub(crate) unsafe fn module_definitions(m: LLVMModuleRef) -> Vec<(CString, JitSymbolFlags)> {
+    let mut definitions = Vec::new();
+    let lists: [(_, unsafe extern "C" fn(LLVMValueRef) -> LLVMValueRef); 3] = [
+        (unsafe { LLVMGetFirstFunction(m) }, LLVMGetNextFunction),
+        (unsafe { LLVMGetFirstGlobal(m) }, LLVMGetNextGlobal),
+        (
+            unsafe { LLVMGetFirstGlobalAlias(m) },
+            LLVMGetNextGlobalAlias,
+        ),
+    ];
+    for (mut gv, next) in lists {
+        while !gv.is_null() {
+            if let Some(definition) = unsafe { definition_flags(gv) } {
+                definitions.push(definition);
+            }
+            gv = unsafe { next(gv) };
+        }
+    }
+    definitions
+}
+
+unsafe fn definition_flags(gv: LLVMValueRef) -> Option<(CString, JitSymbolFlags)> {
+    use LLVMLinkage::*;
+    unsafe {
+        let linkage = LLVMGetLinkage(gv);
+        let mut len = 0;
+        let name = LLVMGetValueName2(gv, &mut len);
+        let skip = matches!(
+            linkage,
+            LLVMInternalLinkage
+                | LLVMPrivateLinkage
+                | LLVMAvailableExternallyLinkage
+                | LLVMAppendingLinkage
+        );
+        if len == 0 || skip || LLVMIsDeclaration(gv) != 0 {
+            return None;
+        }
+        let mut flags = JitSymbolFlags::NONE;
+        if matches!(
+            linkage,
+            LLVMWeakAnyLinkage
+                | LLVMWeakODRLinkage
+                | LLVMLinkOnceAnyLinkage
+                | LLVMLinkOnceODRLinkage
+        ) {
+            flags |= JitSymbolFlags::WEAK;
+        }
+        if LLVMGetVisibility(gv) != LLVMVisibility::LLVMHiddenVisibility {
+            flags |= JitSymbolFlags::EXPORTED;
+        }
+        if LLVMGetTypeKind(LLVMGlobalGetValueType(gv)) == LLVMTypeKind::LLVMFunctionTypeKind {
+            flags |= JitSymbolFlags::CALLABLE;
+        }
+        let name = std::slice::from_raw_parts(name.cast(), len);
+        Some((CString::new(name).ok()?, flags))
+    }
+}
+
+/// A module split off a lazily added one, compiled through the IR transform
+/// layer when first requested.
+struct LazyModule<'j> {
+    tsm: Owning<OrcThreadSafeModule<'j>>,
+    jit: OrcLLJITHandle<'j>,
+    compile_hook: Arc<RwLock<Option<CompileHook>>>,
+    reporter: ErrorReporter,
+    functions: Vec<CString>,
+}
+
+impl MaterializationUnit for LazyModule<'_> {
+    fn materialize(self: Box<Self>, r: MaterializationGuard) {
+        if let Some(hook) = &*self.compile_hook.read().unwrap() {
+            let result = self.tsm.with_module_do(|m| {
+                for name in &self.functions {
+                    hook(name, m);
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                r.fail();
+                report(&self.reporter, e);
+                return;
+            }
+        }
+        let this = *self;
+        this.jit.emit_llvm_ir_module(r, this.tsm);
+    }
+
+    fn discard(&mut self, _jd: &OrcJitDylib, _name: &OrcSymbolStringPoolEntry) {}
+}
@@ -0,0 +1,398 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+use llvm_sys::orc2::*;
+
+use crate::error::Error;
+use crate::orc2::*;
+use crate::owning::Owning;
+
+/// Create a materialization unit defining each alias as a reexport of its
+/// target in `source`. The targets are looked up, and materialized if needed,
+/// only once an alias is.
+///
+/// The unit must be defined in a JITDylib of the session of `source`.
+pub fn reexports(
+    source: &OrcJitDylib,
+    aliases: &[(
+        &OrcSymbolStringPoolEntry,
+        &OrcSymbolStringPoolEntry,
+        JitSymbolFlags,
+    )],
+) -> Owning<OrcMaterializationUnit> {
+    let mut symbols = SymbolFlagsMap::new();
+    for &(alias, _, flags) in aliases {
+        symbols.insert(alias, flags);
+    }
+    let unit = Reexports {
+        source,
+        aliases: aliases
+            .iter()
+            .map(|&(alias, target, flags)| (alias.retain(), target.retain(), flags))
+            .collect(),
+    };
+    // JITDylibs live as long as their session, which destroys the units
+    // defined in it first.
+    unsafe { OrcMaterializationUnit::create_custom_unchecked(c"reexports", symbols, unit) }
+}
+
+struct Reexports<'s> {
+    source: &'s OrcJitDylib,
+    aliases: Vec<(
+        Owning<OrcSymbolStringPoolEntry>,
+        Owning<OrcSymbolStringPoolEntry>,
+        JitSymbolFlags,
+    )>,
+}
+
+impl MaterializationUnit for Reexports<'_> {
+    fn materialize(self: Box<Self>, r: MaterializationGuard) {
+        let es = r.get_execution_session() as *const OrcExecutionSession;
+        let source = self.source;
+        // Dropping the responsibility fails the materialization.
+        let Some(targets) = self
+            .aliases
+            .iter()
+            .map(|(_, target, _)| Some(target.get_str().to_str().ok()?.to_owned()))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let symbols = targets
+            .iter()
+            .map(|target| (target.as_str(), true))
+            .collect::<Vec<_>>();
+        let pending = PendingReexports {
+            r,
+            aliases: self.aliases,
+        };
+        unsafe { &*es }.lookup_async(
+            LLVMOrcLookupKind::LLVMOrcLookupKindStatic,
+            &[(
+                source,
+                LLVMOrcJITDylibLookupFlags::LLVMOrcJITDylibLookupFlagsMatchAllSymbols,
+            )],
+            &symbols,
+            move |result| pending.finish(result),
+        );
+    }
+
+    fn discard(&mut self, _jd: &OrcJitDylib, name: &OrcSymbolStringPoolEntry) {
+        self.aliases
+            .retain(|(alias, _, _)| alias.as_raw() != name.as_raw());
+    }
+}
+
+/// Reexports waiting for their targets, possibly on another thread.
+struct PendingReexports {
+    r: MaterializationGuard,
+    aliases: Vec<(
+        Owning<OrcSymbolStringPoolEntry>,
+        Owning<OrcSymbolStringPoolEntry>,
+        JitSymbolFlags,
+    )>,
+}
+
+impl PendingReexports {
+    fn finish(self, result: LookupResult) {
+        let Self { r, aliases } = self;
+        let result = result.and_then(|found| {
+            let resolved = aliases
+                .iter()
+                .map(|(alias, target, flags)| {
+                    let target = target.get_str().to_string_lossy();
+                    let addr = found.get(&*target).ok_or_else(|| {
+                        Error::Message(format!("reexported symbol {target} was not found"))
+                    })?;
+                    Ok((&**alias, SymbolAddr::new(addr.address, *flags)))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            r.notify_resolved(&resolved)
+        });
+        // Dropping the responsibility fails the materialization.
+        if result.is_ok() {
+            let _ = r.notify_emitted();
+        }
+    }
+}
+
+/// The link orders set by [`OrcJitDylib::set_link_order`], by the address of
+/// their JITDylib. An entry is removed with the generator of its JITDylib,
+/// when the JITDylib is destroyed.
+static LINK_ORDERS: Mutex<BTreeMap<usize, Weak<LinkOrderState>>> = Mutex::new(BTreeMap::new());
+
+fn link_orders() -> MutexGuard<'static, BTreeMap<usize, Weak<LinkOrderState>>> {
+    // The map stays consistent if an insertion or a removal panics.
+    LINK_ORDERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct LinkOrderState {
+    dylibs: Mutex<Vec<(*const OrcJitDylib, LLVMOrcJITDylibLookupFlags)>>,
+}
+
+// SAFETY: the JITDylibs are `Sync`, and only reached while their session lives.
+unsafe impl Send for LinkOrderState {}
+// SAFETY: as above, the list itself is behind a mutex.
+unsafe impl Sync for LinkOrderState {}
+
+impl LinkOrderState {
+    fn dylibs(&self) -> MutexGuard<'_, Vec<(*const OrcJitDylib, LLVMOrcJITDylibLookupFlags)>> {
+        self.dylibs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The link order of a JITDylib, see [`OrcJitDylib::set_link_order`].
+#[derive(Clone)]
+pub struct LinkOrder<'j> {
+    state: Arc<LinkOrderState>,
+    _marker: PhantomData<&'j OrcJitDylib>,
+}
+
+impl<'j> LinkOrder<'j> {
+    /// Replace the JITDylibs searched.
+    pub fn set(&self, dylibs: &[(&'j OrcJitDylib, LLVMOrcJITDylibLookupFlags)]) {
+        *self.state.dylibs() = dylibs
+            .iter()
+            .map(|&(jd, flags)| (jd as *const OrcJitDylib, flags))
+            .collect();
+    }
+
+    pub fn get(&self) -> Vec<(&'j OrcJitDylib, LLVMOrcJITDylibLookupFlags)> {
+        self.state
+            .dylibs()
+            .iter()
+            .map(|&(jd, flags)| (unsafe { &*jd }, flags))
+            .collect()
+    }
+}
+
+impl OrcJitDylib {
+    /// Set the JITDylibs of `es` searched, in order, for the symbols this
+    /// JITDylib does not define itself, replacing the link order set before.
+    /// The first JITDylib defining a symbol shadows the later ones.
+    ///
+    /// The C API has no access to the link order of LLVM, so it is emulated by
+    /// a definition generator, attached on the first call. It looks the
+    /// symbols a lookup needs up in the link order, without blocking the
+    /// lookup, and defines them in this JITDylib as reexports of the JITDylib
+    /// they were found in. The symbols found are materialized, as the lookup
+    /// needs their addresses. A JITDylib whose generator is already searching
+    /// for the same lookup is skipped, so link orders may form cycles.
+    ///
+    /// JITDylibs must not be removed from the session while they are in a
+    /// link order.
+    pub fn set_link_order<'j>(
+        &'j self,
+        es: &'j OrcExecutionSession,
+        dylibs: &[(&'j OrcJitDylib, LLVMOrcJITDylibLookupFlags)],
+    ) -> LinkOrder<'j> {
+        let order = self.link_order_or_attach(es);
+        order.set(dylibs);
+        order
+    }
+
+    /// Append `dylib` to the link order of this JITDylib, see
+    /// [`OrcJitDylib::set_link_order`].
+    pub fn add_to_link_order<'j>(
+        &'j self,
+        es: &'j OrcExecutionSession,
+        dylib: &'j OrcJitDylib,
+        flags: LLVMOrcJITDylibLookupFlags,
+    ) -> LinkOrder<'j> {
+        let order = self.link_order_or_attach(es);
+        order.state.dylibs().push((dylib, flags));
+        order
+    }
+
+    /// The link order set on this JITDylib, if any.
+    pub fn get_link_order(&self) -> Option<LinkOrder<'_>> {
+        let state = link_orders()
+            .get(&(self as *const Self as usize))?
+            .upgrade()?;
+        Some(LinkOrder {
+            state,
+            _marker: PhantomData,
+        })
+    }
+
+    fn link_order_or_attach<'j>(&'j self, es: &'j OrcExecutionSession) -> LinkOrder<'j> {
+        let mut orders = link_orders();
+        let key = self as *const Self as usize;
+        if let Some(state) = orders.get(&key).and_then(Weak::upgrade) {
+            return LinkOrder {
+                state,
+                _marker: PhantomData,
+            };
+        }
+        let state = Arc::new(LinkOrderState {
+            dylibs: Mutex::new(Vec::new()),
+        });
+        orders.insert(key, Arc::downgrade(&state));
+        let generator = LinkOrderGenerator {
+            es,
+            key,
+            state: state.clone(),
+        };
+        // The session and its JITDylibs outlive this JITDylib, and the
+        // generator with it.
+        let dg = unsafe { OrcDefinitionGenerator::create_custom_unchecked(generator) };
+        unsafe { LLVMOrcJITDylibAddGenerator(self.as_raw(), dg.into_raw()) }
+        LinkOrder {
+            state,
+            _marker: PhantomData,
+        }
+    }
+}
+
+thread_local! {
+    /// The JITDylibs whose link order generators run the lookup in progress on
+    /// this thread.
+    static SEARCHING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` as the search of the JITDylibs of `path`, so that lookups it starts
+/// skip them.
+fn searching<R>(path: Vec<usize>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Vec<usize>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SEARCHING.set(std::mem::take(&mut self.0));
+        }
+    }
+    let _restore = Restore(SEARCHING.replace(path));
+    f()
+}
+
+struct LinkOrderGenerator<'j> {
+    es: &'j OrcExecutionSession,
+    key: usize,
+    state: Arc<LinkOrderState>,
+}
+
+impl Drop for LinkOrderGenerator<'_> {
+    fn drop(&mut self) {
+        let mut orders = link_orders();
+        if orders
+            .get(&self.key)
+            .is_some_and(|state| std::ptr::eq(state.as_ptr(), &*self.state))
+        {
+            orders.remove(&self.key);
+        }
+    }
+}
+
+impl AsyncDefinitionGenerator for LinkOrderGenerator<'_> {
+    fn try_to_generate_async(
+        &self,
+        lookup: GeneratorLookup<'_>,
+        jd: &OrcJitDylib,
+        lookup_kind: LLVMOrcLookupKind,
+        names: &[&OrcSymbolStringPoolEntry],
+    ) -> Result<(), Error> {
+        let missing = names
+            .iter()
+            .map(|&name| {
+                let text = name.get_str().to_str().map_err(|_| {
+                    Error::Message(format!(
+                        "search the link order, symbol name {:?} is not valid UTF-8",
+                        name.get_str()
+                    ))
+                })?;
+                Ok((name.retain(), text.to_owned()))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut path = SEARCHING.with_borrow(Clone::clone);
+        path.push(jd as *const OrcJitDylib as usize);
+        let dylibs = self.state.dylibs().clone();
+        Search {
+            es: self.es,
+            jd,
+            kind: lookup_kind,
+            path,
+            dylibs: dylibs.into_iter(),
+            missing,
+            lookup: lookup.suspend(),
+        }
+        .next();
+        Ok(())
+    }
+}
+
+/// A search of the link order of `jd` for the symbols a lookup needs, one
+/// JITDylib after the other.
+struct Search {
+    es: *const OrcExecutionSession,
+    jd: *const OrcJitDylib,
+    kind: LLVMOrcLookupKind,
+    /// The JITDylibs whose generators run this search.
+    path: Vec<usize>,
+    dylibs: std::vec::IntoIter<(*const OrcJitDylib, LLVMOrcJITDylibLookupFlags)>,
+    missing: Vec<(Owning<OrcSymbolStringPoolEntry>, String)>,
+    lookup: SuspendedLookup,
+}
+
+// SAFETY: the session and its JITDylibs are `Sync`, and live until the lookup
+// is resumed.
+unsafe impl Send for Search {}
+
+impl Search {
+    fn next(mut self) {
+        let path = &self.path;
+        let next = self
+            .dylibs
+            .find(|&(source, _)| !path.contains(&(source as usize)));
+        let Some((source, flags)) = next.filter(|_| !self.missing.is_empty()) else {
+            return self.lookup.resume(Ok(()));
+        };
+        let (es, kind, path) = (unsafe { &*self.es }, self.kind, self.path.clone());
+        let names = self
+            .missing
+            .iter()
+            .map(|(_, text)| text.clone())
+            .collect::<Vec<_>>();
+        let symbols = names
+            .iter()
+            .map(|text| (text.as_str(), false))
+            .collect::<Vec<_>>();
+        let found_in = source as usize;
+        searching(path, || {
+            es.lookup_async(
+                kind,
+                &[(unsafe { &*source }, flags)],
+                &symbols,
+                move |result| self.found(found_in, result),
+            )
+        });
+    }
+
+    fn found(mut self, source: usize, result: LookupResult) {
+        let found = match result {
+            Ok(found) => found,
+            Err(e) => return self.lookup.resume(Err(e)),
+        };
+        let mut aliases = Vec::new();
+        self.missing.retain(|(name, text)| match found.get(text) {
+            Some(addr) => {
+                aliases.push((name.retain(), addr.flags));
+                false
+            }
+            None => true,
+        });
+        if !aliases.is_empty() {
+            let aliases = aliases
+                .iter()
+                .map(|(name, flags)| (&**name, &**name, *flags))
+                .collect::<Vec<_>>();
+            let jd = unsafe { &*self.jd };
+            if let Err(e) = jd.define(reexports(
+                unsafe { &*(source as *const OrcJitDylib) },
+                &aliases,
+            )) {
+                return self.lookup.resume(Err(e));
+            }
+        }
+        self.next()
+    }
+}
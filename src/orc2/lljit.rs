@@ -1,13 +1,21 @@
-use std::ffi::{c_char, c_void, CStr};
+use std::ffi::{c_char, c_void, CStr, CString};
 use std::ptr::null_mut;
 
 use llvm_sys::orc2::lljit::*;
 use llvm_sys::orc2::*;
 
-use crate::error::Error;
+use crate::callback::catch_or_abort;
+use crate::data_layout::DataLayout;
+use crate::error::{Error, OpaqueError};
 use crate::orc2::*;
 use crate::owning::{OpaqueDrop, Owning};
 
+/// Options of an [`OrcLLJIT`].
+///
+/// The C API cannot configure the compile threads or the task dispatcher of
+/// LLJIT, so there is no option for concurrent compilation. Code is compiled
+/// on the thread whose lookup first needs it, and lookups through an
+/// [`OrcLLJITHandle`] on several threads compile in parallel.
 #[repr(transparent)]
 pub struct OrcLLJITBuilder {
     _opaque: PhantomOpaque,
@@ -62,23 +70,23 @@ impl OrcLLJITBuilder {
             es: *mut LLVMOrcOpaqueExecutionSession,
             triple: *const c_char,
         ) -> *mut LLVMOrcOpaqueObjectLayer {
-            unsafe {
+            // The creator cannot fail the creation of the JIT, and the builder
+            // keeps no Rust state to carry a panic to `OrcLLJIT::create`.
+            catch_or_abort(|| unsafe {
                 (*(ctx as *const F))(OrcExecutionSession::from_raw(es), CStr::from_ptr(triple))
                     .into_raw()
-            }
+            })
         }
         self.set_object_linking_layer_creator_raw(creator_raw::<F>, Box::into_raw(Box::new(f)) as _)
     }
 }
 
 impl OrcLLJIT {
-    pub fn create(builder: Option<Owning<OrcLLJITBuilder>>) -> Result<Owning<Self>, Owning<Error>> {
+    pub fn create(builder: Option<Owning<OrcLLJITBuilder>>) -> Result<Owning<Self>, Error> {
         unsafe {
             let mut ptr = null_mut();
-            Error::check(LLVMOrcCreateLLJIT(
-                &mut ptr,
-                builder.map(Owning::into_raw).unwrap_or(null_mut()),
-            ))?;
+            let builder = builder.map(Owning::into_raw).unwrap_or(null_mut());
+            OpaqueError::check(LLVMOrcCreateLLJIT(&mut ptr, builder))?;
             Ok(Owning::from_raw(ptr))
         }
     }
@@ -87,7 +95,55 @@ impl OrcLLJIT {
 impl OpaqueDrop for LLVMOrcOpaqueLLJIT {
     unsafe fn drop_raw(ptr: *mut Self) {
         // In fact, currently it always returns success
-        unsafe { Error::check(LLVMOrcDisposeLLJIT(ptr)).unwrap() }
+        unsafe { OpaqueError::check(LLVMOrcDisposeLLJIT(ptr)).unwrap() }
+    }
+}
+
+/// A handle to a JIT to share with worker threads, to add modules to its main
+/// JITDylib and look up symbols concurrently.
+///
+/// The C API does not expose the compile threads of LLJIT, so code is compiled
+/// on the thread whose lookup first needs it. Lookups from several threads
+/// compile in parallel.
+#[derive(Clone, Copy)]
+pub struct OrcLLJITHandle<'j> {
+    jit: &'j OrcLLJIT,
+}
+
+// SAFETY: `LLJIT` sets its layers up on construction, and adds and looks up
+// definitions through its `ExecutionSession`, under its session mutex. What a
+// handle hands over to the JIT may be used on any of its threads, and is bound
+// accordingly: definition generators are `Send + Sync`, materialization units
+// `Send`, and modules are only accessed under the lock of their context.
+unsafe impl Send for OrcLLJITHandle<'_> {}
+// SAFETY: as above, the handle is a shared reference to the JIT.
+unsafe impl Sync for OrcLLJITHandle<'_> {}
+
+impl OrcLLJIT {
+    pub fn handle(&self) -> OrcLLJITHandle<'_> {
+        OrcLLJITHandle { jit: self }
+    }
+}
+
+impl OrcLLJITHandle<'_> {
+    pub fn add_llvm_ir_module(&self, tsm: Owning<OrcThreadSafeModule>) -> Result<(), Error> {
+        self.jit
+            .add_llvm_ir_module(self.jit.get_main_jit_dylib(), tsm)
+    }
+
+    pub fn add_object_file(&self, obj_buffer: Owning<MemoryBuffer>) -> Result<(), Error> {
+        self.jit
+            .add_object_file(self.jit.get_main_jit_dylib(), obj_buffer)
+    }
+
+    pub fn lookup(&self, name: &CStr) -> Result<LLVMOrcExecutorAddress, Error> {
+        self.jit.lookup(name)
+    }
+
+    /// Emit a module through the IR transform layer, for a materialization
+    /// unit responsible for its symbols.
+    pub fn emit_llvm_ir_module(&self, r: MaterializationGuard, tsm: Owning<OrcThreadSafeModule>) {
+        self.jit.get_ir_transform_layer().emit(r.into_inner(), tsm)
     }
 }
 
@@ -121,9 +177,9 @@ impl OrcLLJIT {
         &self,
         jd: &OrcJitDylib,
         obj_buffer: Owning<MemoryBuffer>,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcLLJITAddObjectFile(
+            OpaqueError::check(LLVMOrcLLJITAddObjectFile(
                 self.as_raw(),
                 jd.as_raw(),
                 obj_buffer.into_raw(),
@@ -135,9 +191,9 @@ impl OrcLLJIT {
         &self,
         rt: &OrcResourceTracker,
         obj_buffer: Owning<MemoryBuffer>,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcLLJITAddObjectFileWithRT(
+            OpaqueError::check(LLVMOrcLLJITAddObjectFileWithRT(
                 self.as_raw(),
                 rt.as_raw(),
                 obj_buffer.into_raw(),
@@ -149,9 +205,9 @@ impl OrcLLJIT {
         &self,
         jd: &OrcJitDylib,
         tsm: Owning<OrcThreadSafeModule>,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcLLJITAddLLVMIRModule(
+            OpaqueError::check(LLVMOrcLLJITAddLLVMIRModule(
                 self.as_raw(),
                 jd.as_raw(),
                 tsm.into_raw(),
@@ -163,9 +219,9 @@ impl OrcLLJIT {
         &self,
         rt: &OrcResourceTracker,
         tsm: Owning<OrcThreadSafeModule>,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcLLJITAddLLVMIRModuleWithRT(
+            OpaqueError::check(LLVMOrcLLJITAddLLVMIRModuleWithRT(
                 self.as_raw(),
                 rt.as_raw(),
                 tsm.into_raw(),
@@ -173,10 +229,73 @@ impl OrcLLJIT {
         }
     }
 
-    pub fn lookup(&self, name: &CStr) -> Result<LLVMOrcExecutorAddress, Owning<Error>> {
+    /// Add a module to `jd` under a new resource tracker, so its code can be
+    /// removed or replaced later.
+    pub fn add_llvm_ir_module_tracked<'j>(
+        &self,
+        jd: &'j OrcJitDylib,
+        tsm: Owning<OrcThreadSafeModule>,
+    ) -> Result<ResourceTracker<'j>, Error> {
+        let mut rt = ResourceTracker::new(jd);
+        rt.add_llvm_ir_module(self, tsm)?;
+        Ok(rt)
+    }
+
+    /// Replace the code of `rt` with `tsm`.
+    ///
+    /// Strong definitions cannot be added next to the ones they replace, so
+    /// `tsm` is checked before the old code is removed: its data layout must
+    /// fit the JIT, and its definitions missing from `rt` are claimed in a
+    /// fresh tracker, which fails if any is defined in the JITDylib already.
+    /// Neither check materializes anything. Adding `tsm` can then only fail
+    /// if another thread defines one of its symbols meanwhile.
+    pub fn replace_module(
+        &self,
+        rt: &mut ResourceTracker,
+        tsm: Owning<OrcThreadSafeModule>,
+    ) -> Result<(), Error> {
+        let layout = tsm.with_module_do(|m| {
+            m.get_data_layout_str()
+                .to_string_lossy()
+                .parse::<DataLayout>()
+        })?;
+        self.get_data_layout_str()
+            .to_string_lossy()
+            .parse::<DataLayout>()?
+            .check_compatible(&layout)?;
+        let added = module_symbols(&tsm)?
+            .into_iter()
+            .filter(|name| !rt.defines(name))
+            .collect::<Vec<_>>();
+        let claims = rt.get_jit_dylib().create_resource_tracker();
+        self.claim(&claims, &added).map_err(|e| {
+            Error::Message(format!(
+                "replace module, it defines symbols already defined outside of the resource tracker: {e}"
+            ))
+        })?;
+        unsafe { claims.remove() }?;
+        rt.clear()?;
+        rt.add_llvm_ir_module(self, tsm)
+    }
+
+    /// Define `names` through `rt`, as placeholders that are removed before
+    /// they are looked up.
+    fn claim(&self, rt: &OrcResourceTracker, names: &[CString]) -> Result<(), Error> {
+        let context = OrcThreadSafeContext::create();
+        // The context is new, so nothing else uses it yet.
+        let m = unsafe { context.get_context() }.create_module(c"claims");
+        let ty = m.context().i8_type();
+        for name in names {
+            m.add_global(ty, name)
+                .set_initializer(Some(ty.const_int(0, false)));
+        }
+        self.add_llvm_ir_module_with_rt(rt, context.create_module(m))
+    }
+
+    pub fn lookup(&self, name: &CStr) -> Result<LLVMOrcExecutorAddress, Error> {
         unsafe {
             let mut result = 0;
-            Error::check(LLVMOrcLLJITLookup(
+            OpaqueError::check(LLVMOrcLLJITLookup(
                 self.as_raw(),
                 &mut result,
                 name.as_ptr(),
@@ -203,7 +322,87 @@ impl OrcLLJIT {
         unsafe { CStr::from_ptr(LLVMOrcLLJITGetDataLayoutStr(self.as_raw())) }
     }
 
-    pub fn enable_debug_support(&self) -> Result<(), Owning<Error>> {
-        unsafe { Error::check(LLVMOrcLLJITEnableDebugSupport(self.as_raw())) }
+    pub fn enable_debug_support(&self) -> Result<(), Error> {
+        unsafe { OpaqueError::check(LLVMOrcLLJITEnableDebugSupport(self.as_raw())) }
+    }
+}
+
+/// Adds IR modules to the main JITDylib of a JIT on demand, see
+/// [`OrcLLJIT::add_ir_module_generator`].
+struct IrModuleGenerator<'j, F> {
+    jit: OrcLLJITHandle<'j>,
+    contexts: OrcThreadSafeContextPool,
+    f: F,
+}
+
+impl<F> DefinitionGenerator for IrModuleGenerator<'_, F>
+where
+    F: Fn(&Module, &OrcSymbolStringPoolEntry) -> Result<bool, Error> + Send + Sync,
+{
+    fn try_to_generate(
+        &self,
+        _jd: &OrcJitDylib,
+        _lookup_kind: LLVMOrcLookupKind,
+        names: &[&OrcSymbolStringPoolEntry],
+    ) -> Result<(), Error> {
+        for name in names {
+            let mut defined = false;
+            let tsm = self.contexts.build_module(name.get_str(), |m| {
+                defined = (self.f)(m, name)?;
+                Ok(())
+            })?;
+            if defined {
+                self.jit.add_llvm_ir_module(tsm)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OrcLLJIT {
+    /// Add IR modules to the main JITDylib on demand.
+    ///
+    /// For every name a lookup could not find there, `f` may fill a new module,
+    /// built under the lock of the generator's own context, and returns whether
+    /// the module defines the name. Such modules are added to the main JITDylib
+    /// and compiled as usual. `f` must not define the same symbol twice.
+    pub fn add_ir_module_generator<F>(&self, f: F)
+    where
+        F: Fn(&Module, &OrcSymbolStringPoolEntry) -> Result<bool, Error> + Send + Sync + 'static,
+    {
+        let generator = IrModuleGenerator {
+            jit: self.handle(),
+            contexts: OrcThreadSafeContextPool::new(1),
+            f,
+        };
+        let jd = self.get_main_jit_dylib();
+        // The main JITDylib, and the generator with it, is destroyed with the JIT.
+        let dg = unsafe { OrcDefinitionGenerator::create_custom_unchecked(generator) };
+        unsafe { LLVMOrcJITDylibAddGenerator(jd.as_raw(), dg.into_raw()) }
+    }
+}
+
+impl OrcJitDylib {
+    /// Define symbols implemented by the host process, e.g. runtime helpers
+    /// called from JIT'd code. Names are mangled for the target of `jit`.
+    pub fn define_symbols<'a>(
+        &self,
+        jit: &OrcLLJIT,
+        symbols: impl IntoIterator<Item = (&'a str, SymbolAddr)>,
+    ) -> Result<(), Error> {
+        let symbols = symbols
+            .into_iter()
+            .map(|(name, addr)| {
+                let name = CString::new(name).map_err(|_| {
+                    Error::Message(format!("symbol name {name:?} contains a nul byte"))
+                })?;
+                Ok((jit.mangle_and_intern(&name), addr))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let symbols = symbols
+            .iter()
+            .map(|(name, addr)| (&**name, *addr))
+            .collect::<Vec<_>>();
+        self.define_absolute_symbols(&symbols)
     }
 }
@@ -1,19 +1,26 @@
-use std::ffi::{c_void, CStr};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_void, CStr, CString};
 use std::marker::PhantomData;
-use std::mem::size_of;
-use std::ops::Deref;
+use std::mem::{size_of, ManuallyDrop};
+use std::ops::{BitOr, BitOrAssign, Deref};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr::{null_mut, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use llvm_sys::error::LLVMOpaqueError;
+use llvm_sys::error::{LLVMErrorRef, LLVMOpaqueError};
 use llvm_sys::orc2::*;
+use llvm_sys::prelude::LLVMMemoryBufferRef;
 use llvm_sys::LLVMModule;
 
-use crate::error::Error;
+use crate::callback::{catch_as_error, catch_or_abort, PanicSlot};
+use crate::error::{Error, OpaqueError};
 use crate::owning::{OpaqueClone, OpaqueDrop, Owning};
 use crate::target_machine::TargetMachine;
 use crate::*;
 
 pub mod ee;
+pub mod lazy;
+pub mod linking;
 pub mod lljit;
 
 #[repr(transparent)]
@@ -21,6 +28,10 @@ pub struct OrcExecutionSession {
     _opaque: PhantomOpaque,
 }
 
+// SAFETY: `ExecutionSession` runs every change to its state, including that
+// of its JITDylibs, under its session mutex (`runSessionLocked`).
+unsafe impl Sync for OrcExecutionSession {}
+
 unsafe impl Opaque for OrcExecutionSession {
     type Inner = LLVMOrcOpaqueExecutionSession;
 }
@@ -43,11 +54,25 @@ unsafe impl Opaque for OrcSymbolStringPoolEntry {
     type Inner = LLVMOrcOpaqueSymbolStringPoolEntry;
 }
 
+// SAFETY: `SymbolStringPool` interns under its own mutex, and an entry is an
+// immutable string with a `std::atomic` reference count.
+unsafe impl Send for OrcSymbolStringPoolEntry {}
+// SAFETY: as above, reading an entry does not modify it.
+unsafe impl Sync for OrcSymbolStringPoolEntry {}
+// SAFETY: dropping an owned entry decrements its atomic reference count.
+unsafe impl Send for Owning<OrcSymbolStringPoolEntry> {}
+// SAFETY: as above, cloning increments the atomic reference count.
+unsafe impl Sync for Owning<OrcSymbolStringPoolEntry> {}
+
 #[repr(transparent)]
 pub struct OrcJitDylib {
     _opaque: PhantomOpaque,
 }
 
+// SAFETY: `JITDylib` changes its definitions and generators only under the
+// session mutex of its `ExecutionSession`.
+unsafe impl Sync for OrcJitDylib {}
+
 unsafe impl Opaque for OrcJitDylib {
     type Inner = LLVMOrcOpaqueJITDylib;
 }
@@ -103,7 +128,11 @@ pub struct OrcThreadSafeContext {
     _opaque: PhantomOpaque,
 }
 
+// SAFETY: `ThreadSafeContext` is a `std::shared_ptr` to the context and its
+// `std::recursive_mutex`. The context itself is only reached through the
+// unsafe `get_context`, or under the lock.
 unsafe impl Send for OrcThreadSafeContext {}
+// SAFETY: as above, taking the lock is the only shared access.
 unsafe impl Sync for OrcThreadSafeContext {}
 
 unsafe impl Opaque for OrcThreadSafeContext {
@@ -116,6 +145,21 @@ pub struct OrcThreadSafeModule<'c> {
     _marker: PhantomData<&'c OrcThreadSafeContext>,
 }
 
+// SAFETY: the module is only reached through `with_module_do`, that is
+// `ThreadSafeModule::withModuleDo`, which holds the lock of its context.
+unsafe impl Sync for OrcThreadSafeModule<'_> {}
+
+// SAFETY: disposing a thread-safe context releases its `std::shared_ptr`,
+// whose reference count is atomic. The context is only reached as above.
+unsafe impl Send for Owning<OrcThreadSafeContext> {}
+// SAFETY: as for `OrcThreadSafeContext`.
+unsafe impl Sync for Owning<OrcThreadSafeContext> {}
+// SAFETY: the destructor of `ThreadSafeModule` takes the lock of its context
+// before destroying the module.
+unsafe impl Send for Owning<OrcThreadSafeModule<'_>> {}
+// SAFETY: as for `OrcThreadSafeModule`.
+unsafe impl Sync for Owning<OrcThreadSafeModule<'_>> {}
+
 unsafe impl<'c> Opaque for OrcThreadSafeModule<'c> {
     type Inner = LLVMOrcOpaqueThreadSafeModule;
 }
@@ -175,7 +219,6 @@ unsafe impl Opaque for OrcIndirectStubsManager {
     type Inner = LLVMOrcOpaqueIndirectStubsManager;
 }
 
-#[allow(dead_code)]
 #[repr(transparent)]
 pub struct OrcLazyCallThroughManager {
     _opaque: PhantomOpaque,
@@ -199,6 +242,18 @@ impl OrcExecutionSession {
         unsafe { LLVMOrcExecutionSessionSetErrorReporter(self.as_raw(), report_error, ctx as _) }
     }
 
+    /// Set the function reporting the errors no caller can receive, e.g. the
+    /// failure to compile a lazily called function. It may be called on any
+    /// thread. The reporter is leaked, as the session never releases it.
+    pub fn set_error_reporter<F: Fn(Error) + Send + Sync + 'static>(&self, f: F) {
+        extern "C" fn report_error_raw<F: Fn(Error)>(ctx: *mut c_void, err: LLVMErrorRef) {
+            if let Err(e) = unsafe { OpaqueError::check(err) } {
+                catch_or_abort(|| unsafe { (*(ctx as *const F))(e) })
+            }
+        }
+        self.set_error_reporter_raw(report_error_raw::<F>, Box::into_raw(Box::new(f)) as _)
+    }
+
     pub fn get_symbol_string_pool(&self) -> &OrcSymbolStringPool {
         unsafe {
             OrcSymbolStringPool::from_raw(LLVMOrcExecutionSessionGetSymbolStringPool(self.as_raw()))
@@ -213,22 +268,26 @@ impl OrcSymbolStringPool {
 }
 
 impl OrcExecutionSession {
-    pub fn intern(&self, name: &CStr) -> &OrcSymbolStringPoolEntry {
-        unsafe {
-            OrcSymbolStringPoolEntry::from_raw(LLVMOrcExecutionSessionIntern(
-                self.as_raw(),
-                name.as_ptr(),
-            ))
-        }
+    pub fn intern(&self, name: &CStr) -> Owning<OrcSymbolStringPoolEntry> {
+        unsafe { Owning::from_raw(LLVMOrcExecutionSessionIntern(self.as_raw(), name.as_ptr())) }
     }
 }
 
+pub type LookupResult = Result<HashMap<String, SymbolAddr>, Error>;
+
 impl OrcExecutionSession {
-    pub fn lookup_raw(
+    /// Look up `symbols` in the JITDylibs of `search_order`, calling
+    /// `handle_result` once all of them are found or the lookup fails.
+    ///
+    /// # Safety
+    ///
+    /// The names of `symbols` are taken over by the lookup, so each must have
+    /// been retained for it.
+    pub unsafe fn lookup_raw(
         &self,
         k: LLVMOrcLookupKind,
-        search_order: &LLVMOrcCJITDylibSearchOrderElement,
-        symbols: &LLVMOrcCLookupSetElement,
+        search_order: &[LLVMOrcCJITDylibSearchOrderElement],
+        symbols: &[LLVMOrcCLookupSetElement],
         handle_result: LLVMOrcExecutionSessionLookupHandleResultFunction,
         ctx: *mut (),
     ) {
@@ -236,15 +295,111 @@ impl OrcExecutionSession {
             LLVMOrcExecutionSessionLookup(
                 self.as_raw(),
                 k,
-                search_order as *const _ as *mut _,
-                size_of::<LLVMOrcCJITDylibSearchOrderElement>(),
-                symbols as *const _ as *mut _,
-                size_of::<LLVMOrcCLookupSetElement>(),
+                search_order.as_ptr() as *mut _,
+                search_order.len(),
+                symbols.as_ptr() as *mut _,
+                symbols.len(),
                 handle_result,
                 ctx as _,
             )
         }
     }
+
+    /// Look up `symbols`, given with whether each one is required, in the
+    /// JITDylibs of `search_order`. The names are interned as given, so they
+    /// must be symbol names as they appear in the JITDylib (already mangled,
+    /// see [`lljit::OrcLLJIT::mangle_and_intern`]). `handle_result` may be called on
+    /// another thread, once all required symbols are found or the lookup fails.
+    pub fn lookup_async<F: FnOnce(LookupResult) + Send + 'static>(
+        &self,
+        k: LLVMOrcLookupKind,
+        search_order: &[(&OrcJitDylib, LLVMOrcJITDylibLookupFlags)],
+        symbols: &[(&str, bool)],
+        handle_result: F,
+    ) {
+        extern "C" fn handle_result_raw<F: FnOnce(LookupResult)>(
+            err: LLVMErrorRef,
+            result: LLVMOrcCSymbolMapPairs,
+            num_pairs: usize,
+            ctx: *mut c_void,
+        ) {
+            let f = unsafe { Box::from_raw(ctx as *mut F) };
+            let result = match unsafe { OpaqueError::check(err) } {
+                Ok(()) => Ok(unsafe { std::slice::from_raw_parts(result, num_pairs) }
+                    .iter()
+                    .map(|pair| {
+                        let name = unsafe { OrcSymbolStringPoolEntry::from_raw(pair.Name) };
+                        let addr = SymbolAddr::new(
+                            pair.Sym.Address,
+                            JitSymbolFlags {
+                                generic_flags: pair.Sym.Flags.GenericFlags,
+                                target_flags: pair.Sym.Flags.TargetFlags,
+                            },
+                        );
+                        (name.get_str().to_string_lossy().into_owned(), addr)
+                    })
+                    .collect::<HashMap<_, _>>()),
+                Err(e) => Err(e),
+            };
+            // The lookup may complete on another thread, after `lookup_async`
+            // has returned.
+            catch_or_abort(|| f(result));
+        }
+
+        let names = symbols
+            .iter()
+            .map(|&(name, required)| {
+                let name = CString::new(name).map_err(|_| {
+                    Error::Message(format!("symbol name {name:?} contains a nul byte"))
+                })?;
+                Ok((self.intern(&name), required))
+            })
+            .collect::<Result<Vec<_>, Error>>();
+        let names = match names {
+            Ok(names) => names,
+            Err(e) => return handle_result(Err(e)),
+        };
+        let search_order = search_order
+            .iter()
+            .map(|&(jd, flags)| LLVMOrcCJITDylibSearchOrderElement {
+                JD: jd.as_raw(),
+                JDLookupFlags: flags,
+            })
+            .collect::<Vec<_>>();
+        let symbols = names
+            .into_iter()
+            .map(|(name, required)| LLVMOrcCLookupSetElement {
+                Name: name.into_raw(),
+                LookupFlags: if required {
+                    LLVMOrcSymbolLookupFlags::LLVMOrcSymbolLookupFlagsRequiredSymbol
+                } else {
+                    LLVMOrcSymbolLookupFlags::LLVMOrcSymbolLookupFlagsWeaklyReferencedSymbol
+                },
+            })
+            .collect::<Vec<_>>();
+        let ctx = Box::into_raw(Box::new(handle_result));
+        unsafe { self.lookup_raw(k, &search_order, &symbols, handle_result_raw::<F>, ctx as _) }
+    }
+
+    /// Look up `symbols`, given with whether each one is required, in the
+    /// JITDylibs of `search_order`, blocking until the lookup completes. As for
+    /// [`OrcExecutionSession::lookup_async`], the names must be symbol names as
+    /// they appear in the JITDylib (already mangled).
+    /// Weakly referenced symbols that are not found are missing from the result.
+    pub fn lookup(
+        &self,
+        k: LLVMOrcLookupKind,
+        search_order: &[(&OrcJitDylib, LLVMOrcJITDylibLookupFlags)],
+        symbols: &[(&str, bool)],
+    ) -> LookupResult {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.lookup_async(k, search_order, symbols, move |result| {
+            let _ = sender.send(result);
+        });
+        receiver.recv().unwrap_or(Err(Error::Failed(
+            "look up symbols, the lookup was abandoned".into(),
+        )))
+    }
 }
 
 impl OpaqueClone for LLVMOrcOpaqueSymbolStringPoolEntry {
@@ -264,6 +419,11 @@ impl OrcSymbolStringPoolEntry {
     pub fn get_str(&self) -> &CStr {
         unsafe { CStr::from_ptr(LLVMOrcSymbolStringPoolEntryStr(self.as_raw())) }
     }
+
+    /// Take a new reference to this entry.
+    pub fn retain(&self) -> Owning<OrcSymbolStringPoolEntry> {
+        unsafe { Owning::from_raw(LLVMOrcOpaqueSymbolStringPoolEntry::clone_raw(self.as_raw())) }
+    }
 }
 
 impl OpaqueDrop for LLVMOrcOpaqueResourceTracker {
@@ -277,11 +437,172 @@ impl OrcResourceTracker {
         unsafe { LLVMOrcResourceTrackerTransferTo(self.as_raw(), dst.as_raw()) }
     }
 
-    pub unsafe fn remove(&self) -> Result<(), Owning<Error>> {
-        unsafe { Error::check(LLVMOrcResourceTrackerRemove(self.as_raw())) }
+    pub unsafe fn remove(&self) -> Result<(), Error> {
+        unsafe { OpaqueError::check(LLVMOrcResourceTrackerRemove(self.as_raw())) }
     }
 }
 
+/// An owned resource tracker of a JITDylib.
+///
+/// Dropping the tracker keeps its code in the JITDylib, use
+/// [`ResourceTracker::remove`] to free it. Function handles looked up through
+/// the tracker borrow it, so removing or replacing its code invalidates them.
+/// Only the symbols of modules added through the tracker itself, rather than
+/// through the [`OrcResourceTracker`] it derefs to, can be looked up.
+pub struct ResourceTracker<'j> {
+    jd: &'j OrcJitDylib,
+    rt: Owning<OrcResourceTracker>,
+    symbols: HashSet<CString>,
+}
+
+impl<'j> ResourceTracker<'j> {
+    pub fn new(jd: &'j OrcJitDylib) -> Self {
+        Self {
+            jd,
+            rt: jd.create_resource_tracker(),
+            symbols: HashSet::new(),
+        }
+    }
+
+    pub fn get_jit_dylib(&self) -> &'j OrcJitDylib {
+        self.jd
+    }
+
+    /// Whether a module added through this tracker defines `name`.
+    pub fn defines(&self, name: &CStr) -> bool {
+        self.symbols.contains(name)
+    }
+
+    /// Add a module to the JITDylib, tracking its code.
+    pub fn add_llvm_ir_module(
+        &mut self,
+        jit: &lljit::OrcLLJIT,
+        tsm: Owning<OrcThreadSafeModule>,
+    ) -> Result<(), Error> {
+        let symbols = module_symbols(&tsm)?;
+        jit.add_llvm_ir_module_with_rt(&self.rt, tsm)?;
+        self.symbols.extend(symbols);
+        Ok(())
+    }
+
+    /// Remove all code and definitions added through this tracker.
+    pub fn remove(self) -> Result<(), Error> {
+        unsafe { self.rt.remove() }
+    }
+
+    /// Remove all code and definitions added through this tracker, and start
+    /// tracking afresh.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        // A removed tracker is defunct, so it is replaced by a new one.
+        let rt = std::mem::replace(&mut self.rt, self.jd.create_resource_tracker());
+        self.symbols.clear();
+        unsafe { rt.remove() }
+    }
+
+    /// Move the code and definitions of this tracker to `dst`.
+    pub fn transfer_to(self, dst: &mut ResourceTracker<'j>) {
+        unsafe { self.rt.transfer_to(&dst.rt) }
+        dst.symbols.extend(self.symbols);
+    }
+
+    /// Look up `name` in the JITDylib as a function of type `F`, valid until
+    /// the code of this tracker is removed or replaced.
+    ///
+    /// Fails if no module added through this tracker defines `name`.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be an `extern "C" fn` type matching the signature of the
+    /// function.
+    pub unsafe fn lookup<F: Copy>(
+        &self,
+        jit: &lljit::OrcLLJIT,
+        name: &CStr,
+    ) -> Result<JitFunction<'_, F>, Error> {
+        assert_eq!(size_of::<F>(), size_of::<usize>());
+        if !self.defines(name) {
+            return Err(Error::Message(format!(
+                "look up {name:?}, it is not defined through this resource tracker"
+            )));
+        }
+        let mangled = jit.mangle_and_intern(name);
+        let mangled = mangled.get_str().to_str().map_err(|_| {
+            Error::Message(format!("look up {name:?}, the name is not valid UTF-8"))
+        })?;
+        let found = jit.get_execution_session().lookup(
+            LLVMOrcLookupKind::LLVMOrcLookupKindStatic,
+            &[(
+                self.jd,
+                LLVMOrcJITDylibLookupFlags::LLVMOrcJITDylibLookupFlagsMatchAllSymbols,
+            )],
+            &[(mangled, true)],
+        )?;
+        let addr = found[mangled].address as usize;
+        Ok(JitFunction {
+            f: unsafe { std::mem::transmute_copy(&addr) },
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// The names of the symbols a module defines.
+pub(crate) fn module_symbols(tsm: &OrcThreadSafeModule) -> Result<HashSet<CString>, Error> {
+    tsm.with_module_do(|m| {
+        Ok(unsafe { lazy::module_definitions(m.as_raw()) }
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    })
+}
+
+impl Deref for ResourceTracker<'_> {
+    type Target = OrcResourceTracker;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rt
+    }
+}
+
+/// A typed JIT function borrowing the [`ResourceTracker`] owning its code.
+///
+/// The function can only be called through the handle, so it cannot outlive
+/// the code.
+pub struct JitFunction<'r, F> {
+    f: F,
+    _marker: PhantomData<&'r ResourceTracker<'r>>,
+}
+
+macro_rules! impl_jit_function_call {
+    ($($arg:ident),*) => {
+        impl<R, $($arg),*> JitFunction<'_, extern "C" fn($($arg),*) -> R> {
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn call(&self, $($arg: $arg),*) -> R {
+                (self.f)($($arg),*)
+            }
+        }
+
+        impl<R, $($arg),*> JitFunction<'_, unsafe extern "C" fn($($arg),*) -> R> {
+            /// # Safety
+            ///
+            /// The contract of the function must be upheld.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub unsafe fn call(&self, $($arg: $arg),*) -> R {
+                unsafe { (self.f)($($arg),*) }
+            }
+        }
+    };
+}
+
+impl_jit_function_call!();
+impl_jit_function_call!(A0);
+impl_jit_function_call!(A0, A1);
+impl_jit_function_call!(A0, A1, A2);
+impl_jit_function_call!(A0, A1, A2, A3);
+impl_jit_function_call!(A0, A1, A2, A3, A4);
+impl_jit_function_call!(A0, A1, A2, A3, A4, A5);
+impl_jit_function_call!(A0, A1, A2, A3, A4, A5, A6);
+impl_jit_function_call!(A0, A1, A2, A3, A4, A5, A6, A7);
+
 impl OpaqueDrop for LLVMOrcOpaqueDefinitionGenerator {
     unsafe fn drop_raw(ptr: *mut Self) {
         unsafe { LLVMOrcDisposeDefinitionGenerator(ptr) }
@@ -294,18 +615,205 @@ impl OpaqueDrop for LLVMOrcOpaqueMaterializationUnit {
     }
 }
 
+/// Flags of a JIT symbol, layout-compatible with `LLVMJITSymbolFlags`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JitSymbolFlags {
+    pub generic_flags: u8,
+    pub target_flags: u8,
+}
+
+impl JitSymbolFlags {
+    pub const NONE: Self = Self::generic(LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsNone);
+    pub const EXPORTED: Self =
+        Self::generic(LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported);
+    pub const WEAK: Self = Self::generic(LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsWeak);
+    pub const CALLABLE: Self =
+        Self::generic(LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsCallable);
+    pub const MATERIALIZATION_SIDE_EFFECTS_ONLY: Self = Self::generic(
+        LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsMaterializationSideEffectsOnly,
+    );
+
+    const fn generic(flag: LLVMJITSymbolGenericFlags) -> Self {
+        Self {
+            generic_flags: flag as u8,
+            target_flags: 0,
+        }
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.generic_flags & other.generic_flags == other.generic_flags
+            && self.target_flags & other.target_flags == other.target_flags
+    }
+}
+
+impl BitOr for JitSymbolFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self {
+            generic_flags: self.generic_flags | rhs.generic_flags,
+            target_flags: self.target_flags | rhs.target_flags,
+        }
+    }
+}
+
+impl BitOrAssign for JitSymbolFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = *self | rhs;
+    }
+}
+
+impl From<JitSymbolFlags> for LLVMJITSymbolFlags {
+    fn from(value: JitSymbolFlags) -> Self {
+        LLVMJITSymbolFlags {
+            GenericFlags: value.generic_flags,
+            TargetFlags: value.target_flags,
+        }
+    }
+}
+
+impl From<LLVMJITSymbolFlags> for JitSymbolFlags {
+    fn from(value: LLVMJITSymbolFlags) -> Self {
+        JitSymbolFlags {
+            generic_flags: value.GenericFlags,
+            target_flags: value.TargetFlags,
+        }
+    }
+}
+
 #[repr(C)]
 pub struct OrcCSymbolFlagsMapPair<'e> {
     pub name: &'e OrcSymbolStringPoolEntry,
-    pub flags: LLVMJITSymbolFlags,
+    pub flags: JitSymbolFlags,
+}
+
+/// The symbols provided by a materialization unit, each holding a reference
+/// to its name.
+#[derive(Default)]
+pub struct SymbolFlagsMap {
+    symbols: Vec<(Owning<OrcSymbolStringPoolEntry>, JitSymbolFlags)>,
+    init_symbol: Option<Owning<OrcSymbolStringPoolEntry>>,
+}
+
+impl SymbolFlagsMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &OrcSymbolStringPoolEntry, flags: JitSymbolFlags) -> &mut Self {
+        self.symbols.push((name.retain(), flags));
+        self
+    }
+
+    /// Set the symbol whose materialization runs the unit's static initializers.
+    pub fn set_init_symbol(&mut self, name: &OrcSymbolStringPoolEntry) -> &mut Self {
+        assert!(self.init_symbol.is_none(), "init symbol already set");
+        self.insert(name, JitSymbolFlags::MATERIALIZATION_SIDE_EFFECTS_ONLY);
+        self.init_symbol = Some(name.retain());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// A set of definitions materialized on demand, e.g. by compiling a function
+/// the first time one of its symbols is looked up.
+///
+/// Units are materialized on the thread of the lookup first needing them.
+pub trait MaterializationUnit: Send {
+    /// Produce the definitions. Implementations resolve and emit the symbols
+    /// through `r`, or hand it over to a layer emitting them.
+    ///
+    /// `r` is the `Owning<OrcMaterializationResponsibility>` of the unit in a
+    /// [`MaterializationGuard`], which derefs to it and gives it up with
+    /// [`MaterializationGuard::into_inner`]. A responsibility dropped before
+    /// its symbols are emitted leaves the lookups waiting on them hanging, so
+    /// the guard fails their materialization instead when `materialize`
+    /// returns early or panics.
+    fn materialize(self: Box<Self>, r: MaterializationGuard);
+
+    /// Called when the definition of `name` is overridden by a stronger one;
+    /// it won't be requested from this unit any more.
+    fn discard(&mut self, jd: &OrcJitDylib, name: &OrcSymbolStringPoolEntry);
+}
+
+/// The responsibility for the symbols of a [`MaterializationUnit`].
+///
+/// Dropping it before the symbols are emitted, e.g. when `materialize` returns
+/// early or panics, fails their materialization, so that lookups waiting on
+/// them fail rather than hang.
+pub struct MaterializationGuard {
+    r: Option<Owning<OrcMaterializationResponsibility>>,
+}
+
+// SAFETY: `MaterializationResponsibility` reports to its `ExecutionSession`,
+// which updates the symbols under its session mutex, so ORC fulfils or fails
+// responsibilities on the threads of its dispatcher.
+unsafe impl Send for MaterializationGuard {}
+
+impl MaterializationGuard {
+    pub fn new(r: Owning<OrcMaterializationResponsibility>) -> Self {
+        Self { r: Some(r) }
+    }
+
+    /// Notify that the symbols are emitted, which releases the
+    /// responsibility. On error, their materialization fails.
+    pub fn notify_emitted(mut self) -> Result<(), Error> {
+        self.r.as_ref().unwrap().notify_emitted()?;
+        self.r = None;
+        Ok(())
+    }
+
+    /// Fail the materialization of the symbols, which releases the
+    /// responsibility.
+    pub fn fail(mut self) {
+        let r = self.r.take().unwrap();
+        unsafe { r.fail_materialization() }
+    }
+
+    /// Hand the responsibility over, e.g. to a layer emitting the symbols.
+    pub fn into_inner(mut self) -> Owning<OrcMaterializationResponsibility> {
+        self.r.take().unwrap()
+    }
+}
+
+impl Deref for MaterializationGuard {
+    type Target = OrcMaterializationResponsibility;
+
+    fn deref(&self) -> &Self::Target {
+        self.r.as_ref().unwrap()
+    }
+}
+
+impl Drop for MaterializationGuard {
+    fn drop(&mut self) {
+        if let Some(r) = self.r.take() {
+            unsafe { r.fail_materialization() }
+        }
+    }
 }
 
 impl OrcMaterializationUnit {
-    pub fn create_custom_raw(
+    /// Create a materialization unit from raw callbacks.
+    ///
+    /// # Safety
+    ///
+    /// Takes ownership of one reference to every name in `syms`, and of one
+    /// more to `init_sym`. `ctx` must stay valid until either `materialize` or
+    /// `destroy` is called.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn create_custom_raw(
         name: &CStr,
-        ctx: &mut (),
-        syms: &[&OrcCSymbolFlagsMapPair],
-        init_sym: OrcSymbolStringPoolEntry,
+        ctx: *mut (),
+        syms: &[OrcCSymbolFlagsMapPair],
+        init_sym: Option<&OrcSymbolStringPoolEntry>,
         materialize: LLVMOrcMaterializationUnitMaterializeFunction,
         discard: LLVMOrcMaterializationUnitDiscardFunction,
         destroy: LLVMOrcMaterializationUnitDestroyFunction,
@@ -313,40 +821,209 @@ impl OrcMaterializationUnit {
         unsafe {
             Owning::from_raw(LLVMOrcCreateCustomMaterializationUnit(
                 name.as_ptr(),
-                ctx as *mut _ as *mut _,
+                ctx as _,
                 syms.as_ptr() as _,
                 syms.len(),
-                init_sym.as_raw(),
+                init_sym.map(|s| s.as_raw()).unwrap_or(null_mut()),
                 materialize,
                 discard,
                 destroy,
             ))
         }
     }
+
+    pub fn create_custom<M: MaterializationUnit + 'static>(
+        name: &CStr,
+        symbols: SymbolFlagsMap,
+        unit: M,
+    ) -> Owning<OrcMaterializationUnit> {
+        unsafe { Self::create_custom_unchecked(name, symbols, unit) }
+    }
+
+    /// # Safety
+    ///
+    /// `unit` must outlive the JITDylib the unit is defined in.
+    pub(crate) unsafe fn create_custom_unchecked<M: MaterializationUnit>(
+        name: &CStr,
+        symbols: SymbolFlagsMap,
+        unit: M,
+    ) -> Owning<OrcMaterializationUnit> {
+        extern "C" fn materialize_raw<M: MaterializationUnit>(
+            ctx: *mut c_void,
+            mr: *mut LLVMOrcOpaqueMaterializationResponsibility,
+        ) {
+            // Materialization runs on whichever thread first needs the
+            // symbols, with no caller to resume a panic in. The panic hook
+            // reports it, and the guard fails the materialization.
+            let unit = unsafe { Box::from_raw(ctx as *mut M) };
+            let r = MaterializationGuard::new(unsafe { Owning::from_raw(mr) });
+            let _ = catch_unwind(AssertUnwindSafe(|| unit.materialize(r)));
+        }
+        extern "C" fn discard_raw<M: MaterializationUnit>(
+            ctx: *mut c_void,
+            jd: *mut LLVMOrcOpaqueJITDylib,
+            name: *mut LLVMOrcOpaqueSymbolStringPoolEntry,
+        ) {
+            catch_or_abort(|| unsafe {
+                (*(ctx as *mut M)).discard(
+                    OrcJitDylib::from_raw(jd),
+                    OrcSymbolStringPoolEntry::from_raw(name),
+                )
+            })
+        }
+        extern "C" fn destroy_raw<M>(ctx: *mut c_void) {
+            catch_or_abort(|| unsafe { drop(Box::from_raw(ctx as *mut M)) })
+        }
+
+        let SymbolFlagsMap {
+            symbols,
+            init_symbol,
+        } = symbols;
+        let pairs = symbols
+            .iter()
+            .map(|(name, flags)| OrcCSymbolFlagsMapPair {
+                name,
+                flags: *flags,
+            })
+            .collect::<Vec<_>>();
+        let mu = unsafe {
+            Self::create_custom_raw(
+                name,
+                Box::into_raw(Box::new(unit)) as _,
+                &pairs,
+                init_symbol.as_deref(),
+                materialize_raw::<M>,
+                discard_raw::<M>,
+                destroy_raw::<M>,
+            )
+        };
+        drop(pairs);
+        // The references are now owned by the materialization unit.
+        for (name, _) in symbols {
+            name.into_raw();
+        }
+        if let Some(name) = init_symbol {
+            name.into_raw();
+        }
+        mu
+    }
 }
 
-pub fn absolute_symbols<'m>(
-    syms: &[(&'m OrcSymbolStringPoolEntry, LLVMJITEvaluatedSymbol)],
-) -> &'m OrcMaterializationUnit {
-    unsafe {
-        OrcMaterializationUnit::from_raw(LLVMOrcAbsoluteSymbols(syms.as_ptr() as _, syms.len()))
+/// The address and flags of a symbol defined outside of the JIT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolAddr {
+    pub address: LLVMOrcExecutorAddress,
+    pub flags: JitSymbolFlags,
+}
+
+impl SymbolAddr {
+    pub const fn new(address: LLVMOrcExecutorAddress, flags: JitSymbolFlags) -> Self {
+        Self { address, flags }
+    }
+
+    /// An exported, callable symbol at `f`.
+    pub fn function(f: *const ()) -> Self {
+        Self::new(f as _, JitSymbolFlags::EXPORTED | JitSymbolFlags::CALLABLE)
+    }
+
+    /// An exported data symbol at `value`.
+    pub fn from_static<T: Sync>(value: &'static T) -> Self {
+        Self::new(value as *const T as _, JitSymbolFlags::EXPORTED)
     }
 }
 
+macro_rules! impl_symbol_addr_from_fn {
+    ($($arg:ident),*) => {
+        impl<R, $($arg),*> From<extern "C" fn($($arg),*) -> R> for SymbolAddr {
+            fn from(f: extern "C" fn($($arg),*) -> R) -> Self {
+                Self::function(f as *const ())
+            }
+        }
+
+        impl<R, $($arg),*> From<unsafe extern "C" fn($($arg),*) -> R> for SymbolAddr {
+            fn from(f: unsafe extern "C" fn($($arg),*) -> R) -> Self {
+                Self::function(f as *const ())
+            }
+        }
+    };
+}
+
+impl_symbol_addr_from_fn!();
+impl_symbol_addr_from_fn!(A0);
+impl_symbol_addr_from_fn!(A0, A1);
+impl_symbol_addr_from_fn!(A0, A1, A2);
+impl_symbol_addr_from_fn!(A0, A1, A2, A3);
+impl_symbol_addr_from_fn!(A0, A1, A2, A3, A4);
+impl_symbol_addr_from_fn!(A0, A1, A2, A3, A4, A5);
+impl_symbol_addr_from_fn!(A0, A1, A2, A3, A4, A5, A6);
+impl_symbol_addr_from_fn!(A0, A1, A2, A3, A4, A5, A6, A7);
+
+/// Create a materialization unit defining symbols at fixed addresses.
+pub fn absolute_symbols(
+    syms: &[(&OrcSymbolStringPoolEntry, SymbolAddr)],
+) -> Owning<OrcMaterializationUnit> {
+    // `LLVMOrcAbsoluteSymbols` takes ownership of one reference to each name.
+    let mut pairs = syms
+        .iter()
+        .map(|(name, sym)| LLVMOrcCSymbolMapPair {
+            Name: name.retain().into_raw(),
+            Sym: LLVMJITEvaluatedSymbol {
+                Address: sym.address,
+                Flags: sym.flags.into(),
+            },
+        })
+        .collect::<Vec<_>>();
+    unsafe { Owning::from_raw(LLVMOrcAbsoluteSymbols(pairs.as_mut_ptr(), pairs.len())) }
+}
+
 impl OrcLazyCallThroughManager {
+    pub fn create_local(
+        target_triple: impl AsRef<CStr>,
+        es: &OrcExecutionSession,
+        error_handler_addr: LLVMOrcJITTargetAddress,
+    ) -> Result<Owning<OrcLazyCallThroughManager>, Error> {
+        unsafe {
+            let mut result = null_mut();
+            OpaqueError::check(LLVMOrcCreateLocalLazyCallThroughManager(
+                target_triple.as_ref().as_ptr(),
+                es.as_raw(),
+                error_handler_addr,
+                &mut result,
+            ))?;
+            Ok(Owning::from_raw(result))
+        }
+    }
+
+    /// Create a materialization unit defining each alias name as a stub that
+    /// looks up and compiles the aliasee in `source_ref` on first call.
     pub fn lazy_reexports(
         &self,
         ism: &OrcIndirectStubsManager,
         source_ref: &OrcJitDylib,
-        callable_aliases: &[&LLVMOrcCSymbolAliasMapPair],
-    ) -> &OrcMaterializationUnit {
+        callable_aliases: &[(
+            &OrcSymbolStringPoolEntry,
+            &OrcSymbolStringPoolEntry,
+            JitSymbolFlags,
+        )],
+    ) -> Owning<OrcMaterializationUnit> {
+        // `LLVMOrcLazyReexports` takes ownership of one reference to each name.
+        let mut pairs = callable_aliases
+            .iter()
+            .map(|(name, aliasee, flags)| LLVMOrcCSymbolAliasMapPair {
+                Name: name.retain().into_raw(),
+                Entry: LLVMOrcCSymbolAliasMapEntry {
+                    Name: aliasee.retain().into_raw(),
+                    Flags: (*flags).into(),
+                },
+            })
+            .collect::<Vec<_>>();
         unsafe {
-            OrcMaterializationUnit::from_raw(LLVMOrcLazyReexports(
+            Owning::from_raw(LLVMOrcLazyReexports(
                 self.as_raw(),
                 ism.as_raw(),
                 source_ref.as_raw(),
-                callable_aliases.as_ptr() as _,
-                callable_aliases.len(),
+                pairs.as_mut_ptr(),
+                pairs.len(),
             ))
         }
     }
@@ -444,33 +1121,42 @@ impl<'m> Drop for Symbols<'m> {
 }
 
 impl OrcMaterializationResponsibility {
+    /// Report the addresses of the symbols being materialized.
     pub fn notify_resolved(
         &self,
-        symbols: &[&OrcSymbolStringPoolEntry],
-    ) -> Result<(), Owning<Error>> {
+        symbols: &[(&OrcSymbolStringPoolEntry, SymbolAddr)],
+    ) -> Result<(), Error> {
+        // Like `LLVMOrcAbsoluteSymbols`, this takes ownership of the names.
+        let mut pairs = symbols
+            .iter()
+            .map(|(name, sym)| LLVMOrcCSymbolMapPair {
+                Name: name.retain().into_raw(),
+                Sym: LLVMJITEvaluatedSymbol {
+                    Address: sym.address,
+                    Flags: sym.flags.into(),
+                },
+            })
+            .collect::<Vec<_>>();
         unsafe {
-            Error::check(LLVMOrcMaterializationResponsibilityNotifyResolved(
+            OpaqueError::check(LLVMOrcMaterializationResponsibilityNotifyResolved(
                 self.as_raw(),
-                symbols.as_ptr() as _,
-                symbols.len(),
+                pairs.as_mut_ptr(),
+                pairs.len(),
             ))
         }
     }
 
-    pub fn notify_emitted(&self) -> Result<(), Owning<Error>> {
+    pub fn notify_emitted(&self) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcMaterializationResponsibilityNotifyEmitted(
+            OpaqueError::check(LLVMOrcMaterializationResponsibilityNotifyEmitted(
                 self.as_raw(),
             ))
         }
     }
 
-    pub fn define_materializing(
-        &self,
-        pairs: &[&OrcCSymbolFlagsMapPair],
-    ) -> Result<(), Owning<Error>> {
+    pub fn define_materializing(&self, pairs: &[OrcCSymbolFlagsMapPair]) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcMaterializationResponsibilityDefineMaterializing(
+            OpaqueError::check(LLVMOrcMaterializationResponsibilityDefineMaterializing(
                 self.as_raw(),
                 pairs.as_ptr() as _,
                 pairs.len(),
@@ -482,9 +1168,9 @@ impl OrcMaterializationResponsibility {
         unsafe { LLVMOrcMaterializationResponsibilityFailMaterialization(self.as_raw()) }
     }
 
-    pub unsafe fn replace(&self, mu: &OrcMaterializationUnit) -> Result<(), Owning<Error>> {
+    pub unsafe fn replace(&self, mu: &OrcMaterializationUnit) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcMaterializationResponsibilityReplace(
+            OpaqueError::check(LLVMOrcMaterializationResponsibilityReplace(
                 self.as_raw(),
                 mu.as_raw(),
             ))
@@ -494,10 +1180,10 @@ impl OrcMaterializationResponsibility {
     pub fn delegate(
         &self,
         symbols: &[&OrcSymbolStringPoolEntry],
-    ) -> Result<&OrcMaterializationResponsibility, Owning<Error>> {
+    ) -> Result<&OrcMaterializationResponsibility, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcMaterializationResponsibilityDelegate(
+            OpaqueError::check(LLVMOrcMaterializationResponsibilityDelegate(
                 self.as_raw(),
                 symbols.as_ptr() as _,
                 symbols.len(),
@@ -551,10 +1237,10 @@ impl OrcExecutionSession {
         }
     }
 
-    pub fn create_jit_dylib(&self, name: &CStr) -> Result<&OrcJitDylib, Owning<Error>> {
+    pub fn create_jit_dylib(&self, name: &CStr) -> Result<&OrcJitDylib, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcExecutionSessionCreateJITDylib(
+            OpaqueError::check(LLVMOrcExecutionSessionCreateJITDylib(
                 self.as_raw(),
                 &mut result,
                 name.as_ptr(),
@@ -574,8 +1260,8 @@ impl OrcExecutionSession {
 }
 
 impl OrcJitDylib {
-    pub fn create_resource_tracker(&self) -> &OrcResourceTracker {
-        unsafe { OrcResourceTracker::from_raw(LLVMOrcJITDylibCreateResourceTracker(self.as_raw())) }
+    pub fn create_resource_tracker(&self) -> Owning<OrcResourceTracker> {
+        unsafe { Owning::from_raw(LLVMOrcJITDylibCreateResourceTracker(self.as_raw())) }
     }
 
     pub fn get_default_resource_tracker(&self) -> &OrcResourceTracker {
@@ -584,17 +1270,185 @@ impl OrcJitDylib {
         }
     }
 
-    pub fn define(&self, mu: &OrcMaterializationUnit) -> Result<(), Owning<Error>> {
-        unsafe { Error::check(LLVMOrcJITDylibDefine(self.as_raw(), mu.as_raw())) }
+    /// Add the definitions of `mu`. The JITDylib takes ownership of it on
+    /// success, otherwise it is disposed.
+    pub fn define(&self, mu: Owning<OrcMaterializationUnit>) -> Result<(), Error> {
+        unsafe {
+            let mu = mu.into_raw();
+            let err = LLVMOrcJITDylibDefine(self.as_raw(), mu);
+            if !err.is_null() {
+                LLVMOrcDisposeMaterializationUnit(mu);
+            }
+            OpaqueError::check(err)
+        }
     }
 
-    pub unsafe fn clear(&self) -> Result<(), Owning<Error>> {
-        unsafe { Error::check(LLVMOrcJITDylibClear(self.as_raw())) }
+    pub unsafe fn clear(&self) -> Result<(), Error> {
+        unsafe { OpaqueError::check(LLVMOrcJITDylibClear(self.as_raw())) }
     }
 
     pub fn add_generator(&self, dg: &OrcDefinitionGenerator) {
         unsafe { LLVMOrcJITDylibAddGenerator(self.as_raw(), dg.as_raw()) }
     }
+
+    /// Attach `generator` to this JITDylib, which takes ownership of it.
+    pub fn add_definition_generator<G: AsyncDefinitionGenerator + 'static>(&self, generator: G) {
+        let dg = OrcDefinitionGenerator::create_custom(generator);
+        unsafe { LLVMOrcJITDylibAddGenerator(self.as_raw(), dg.into_raw()) }
+    }
+
+    /// Define symbols at fixed addresses.
+    pub fn define_absolute_symbols(
+        &self,
+        syms: &[(&OrcSymbolStringPoolEntry, SymbolAddr)],
+    ) -> Result<(), Error> {
+        self.define(absolute_symbols(syms))
+    }
+}
+
+/// Generates definitions on demand for symbols a lookup could not find.
+///
+/// Lookups run on any thread, concurrently, as long as the JITDylib lives.
+pub trait DefinitionGenerator: Send + Sync {
+    /// Called with the `names` a lookup could not find in `jd`. Define any of
+    /// them in `jd`, e.g. with [`OrcJitDylib::define_absolute_symbols`]; the ones
+    /// left undefined are reported as missing symbols.
+    fn try_to_generate(
+        &self,
+        jd: &OrcJitDylib,
+        lookup_kind: LLVMOrcLookupKind,
+        names: &[&OrcSymbolStringPoolEntry],
+    ) -> Result<(), Error>;
+}
+
+/// A [`DefinitionGenerator`] that may suspend the lookup it generates for, to
+/// define the symbols once other lookups complete rather than block on them.
+pub trait AsyncDefinitionGenerator: Send + Sync {
+    /// Called with the `names` a lookup could not find in `jd`. Either define
+    /// any of them before returning, or take over the lookup with
+    /// [`GeneratorLookup::suspend`] and resume it once they are defined.
+    fn try_to_generate_async(
+        &self,
+        lookup: GeneratorLookup<'_>,
+        jd: &OrcJitDylib,
+        lookup_kind: LLVMOrcLookupKind,
+        names: &[&OrcSymbolStringPoolEntry],
+    ) -> Result<(), Error>;
+}
+
+impl<G: DefinitionGenerator> AsyncDefinitionGenerator for G {
+    fn try_to_generate_async(
+        &self,
+        _lookup: GeneratorLookup<'_>,
+        jd: &OrcJitDylib,
+        lookup_kind: LLVMOrcLookupKind,
+        names: &[&OrcSymbolStringPoolEntry],
+    ) -> Result<(), Error> {
+        self.try_to_generate(jd, lookup_kind, names)
+    }
+}
+
+/// The lookup a definition generator is called for.
+pub struct GeneratorLookup<'a> {
+    state: &'a mut LLVMOrcLookupStateRef,
+}
+
+impl GeneratorLookup<'_> {
+    /// Take over the lookup, which waits until [`SuspendedLookup::resume`] is
+    /// called. The generator must then return `Ok`, and report any failure
+    /// through `resume`.
+    pub fn suspend(self) -> SuspendedLookup {
+        SuspendedLookup {
+            state: std::mem::replace(self.state, null_mut()),
+        }
+    }
+}
+
+/// A lookup suspended by its definition generator, see
+/// [`GeneratorLookup::suspend`]. Dropping it without resuming fails the lookup,
+/// so that it does not hang.
+pub struct SuspendedLookup {
+    state: LLVMOrcLookupStateRef,
+}
+
+// SAFETY: a `LookupState` owns the state of its lookup, which ORC continues on
+// whichever thread calls `continueLookup`.
+unsafe impl Send for SuspendedLookup {}
+
+impl SuspendedLookup {
+    /// Continue the lookup, once the generator defined what it could, or fail
+    /// it.
+    pub fn resume(mut self, result: Result<(), Error>) {
+        let state = std::mem::replace(&mut self.state, null_mut());
+        unsafe { OrcLookupState::from_raw(state) }.continue_lookup(result.err());
+    }
+}
+
+impl Drop for SuspendedLookup {
+    fn drop(&mut self) {
+        if !self.state.is_null() {
+            let error = Error::Failed("generate definitions, the lookup was dropped".into());
+            unsafe { OrcLookupState::from_raw(self.state) }.continue_lookup(Some(error));
+        }
+    }
+}
+
+impl OrcDefinitionGenerator {
+    pub fn create_custom<G: AsyncDefinitionGenerator + 'static>(
+        generator: G,
+    ) -> Owning<OrcDefinitionGenerator> {
+        unsafe { Self::create_custom_unchecked(generator) }
+    }
+
+    /// # Safety
+    ///
+    /// `generator` must outlive the JITDylib the generator is attached to.
+    pub(crate) unsafe fn create_custom_unchecked<G: AsyncDefinitionGenerator>(
+        generator: G,
+    ) -> Owning<OrcDefinitionGenerator> {
+        extern "C" fn try_to_generate_raw<G: AsyncDefinitionGenerator>(
+            _generator: *mut LLVMOrcOpaqueDefinitionGenerator,
+            ctx: *mut c_void,
+            lookup_state: *mut LLVMOrcLookupStateRef,
+            kind: LLVMOrcLookupKind,
+            jd: *mut LLVMOrcOpaqueJITDylib,
+            _jd_lookup_flags: LLVMOrcJITDylibLookupFlags,
+            lookup_set: LLVMOrcCLookupSet,
+            lookup_set_size: usize,
+        ) -> *mut LLVMOpaqueError {
+            let result = catch_as_error("generate definitions", || unsafe {
+                let names = if lookup_set_size == 0 {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts(lookup_set, lookup_set_size)
+                        .iter()
+                        .map(|e| OrcSymbolStringPoolEntry::from_raw(e.Name))
+                        .collect()
+                };
+                let lookup = GeneratorLookup {
+                    state: &mut *lookup_state,
+                };
+                (*(ctx as *const G)).try_to_generate_async(
+                    lookup,
+                    OrcJitDylib::from_raw(jd),
+                    kind,
+                    &names,
+                )
+            });
+            match result {
+                Ok(()) => null_mut(),
+                Err(e) => e.into_opaque().into_raw(),
+            }
+        }
+        extern "C" fn dispose_raw<G>(ctx: *mut c_void) {
+            catch_or_abort(|| unsafe { drop(Box::from_raw(ctx as *mut G)) })
+        }
+        Self::create_custom_c_api_definition_generator_raw(
+            try_to_generate_raw::<G>,
+            Box::into_raw(Box::new(generator)) as _,
+            dispose_raw::<G>,
+        )
+    }
 }
 
 impl OrcDefinitionGenerator {
@@ -612,11 +1466,13 @@ impl OrcDefinitionGenerator {
 }
 
 impl OrcLookupState {
-    pub fn continue_lookup(&self, error: Option<Owning<Error>>) {
+    pub fn continue_lookup(&self, error: Option<Error>) {
         unsafe {
             LLVMOrcLookupStateContinueLookup(
                 self.as_raw(),
-                error.map(Owning::into_raw).unwrap_or(null_mut()),
+                error
+                    .map(|e| e.into_opaque().into_raw())
+                    .unwrap_or(null_mut()),
             )
         }
     }
@@ -627,10 +1483,10 @@ impl OrcDefinitionGenerator {
         global_prefix: u8,
         filter: LLVMOrcSymbolPredicate,
         ctx: *mut (),
-    ) -> Result<Owning<OrcDefinitionGenerator>, Owning<Error>> {
+    ) -> Result<Owning<OrcDefinitionGenerator>, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcCreateDynamicLibrarySearchGeneratorForProcess(
+            OpaqueError::check(LLVMOrcCreateDynamicLibrarySearchGeneratorForProcess(
                 &mut result,
                 global_prefix as _,
                 filter,
@@ -645,10 +1501,10 @@ impl OrcDefinitionGenerator {
         global_prefix: u8,
         filter: LLVMOrcSymbolPredicate,
         ctx: *mut (),
-    ) -> Result<Owning<OrcDefinitionGenerator>, Owning<Error>> {
+    ) -> Result<Owning<OrcDefinitionGenerator>, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcCreateDynamicLibrarySearchGeneratorForPath(
+            OpaqueError::check(LLVMOrcCreateDynamicLibrarySearchGeneratorForPath(
                 &mut result,
                 file_name.as_ptr(),
                 global_prefix as _,
@@ -663,15 +1519,15 @@ impl OrcDefinitionGenerator {
     pub fn create_static_library_search_generator_for_path_raw(
         obj_layer: &OrcObjectLayer,
         file_name: &CStr,
-        target_triple: &CStr,
-    ) -> Result<Owning<OrcDefinitionGenerator>, Owning<Error>> {
+        target_triple: impl AsRef<CStr>,
+    ) -> Result<Owning<OrcDefinitionGenerator>, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcCreateStaticLibrarySearchGeneratorForPath(
+            OpaqueError::check(LLVMOrcCreateStaticLibrarySearchGeneratorForPath(
                 &mut result,
                 obj_layer.as_raw(),
                 file_name.as_ptr(),
-                target_triple.as_ptr(),
+                target_triple.as_ref().as_ptr(),
             ))?;
             Ok(Owning::from_raw(result))
         }
@@ -683,7 +1539,14 @@ impl OrcThreadSafeContext {
         unsafe { Owning::from_raw(LLVMOrcCreateNewThreadSafeContext()) }
     }
 
-    pub fn get_context(&self) -> &Context {
+    /// The context guarded by this thread-safe context.
+    ///
+    /// # Safety
+    ///
+    /// The context is shared with the JIT, which uses it under its lock. It must
+    /// only be used while no module of this context is being compiled, or under
+    /// that lock, e.g. from [`OrcThreadSafeModule::with_module_do`].
+    pub unsafe fn get_context(&self) -> &Context {
         unsafe { Context::from_raw(LLVMOrcThreadSafeContextGetContext(self.as_raw())) }
     }
 }
@@ -704,6 +1567,68 @@ impl OrcThreadSafeContext {
     }
 }
 
+/// A pool of thread-safe contexts to build modules in from several threads.
+///
+/// Modules are built under the lock of their context, the lock the JIT takes to
+/// compile them, so building never races with compiling. Spreading modules
+/// over several contexts lets them be built in parallel.
+pub struct OrcThreadSafeContextPool {
+    slots: Vec<ContextSlot>,
+    next: AtomicUsize,
+}
+
+struct ContextSlot {
+    // An empty module whose `with_module_do` takes the lock of `context`.
+    anchor: Owning<OrcThreadSafeModule<'static>>,
+    context: Owning<OrcThreadSafeContext>,
+}
+
+impl OrcThreadSafeContextPool {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "a context pool needs at least one context");
+        let slots = (0..size)
+            .map(|_| {
+                let context = OrcThreadSafeContext::create();
+                // The context is new, so nothing else uses it yet.
+                let anchor = context
+                    .create_module(unsafe { context.get_context() }.create_module(c"anchor"));
+                ContextSlot {
+                    anchor: unsafe { Owning::from_raw(anchor.into_raw()) },
+                    context,
+                }
+            })
+            .collect();
+        Self {
+            slots,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Create a module named `name` in one of the contexts, and build it with
+    /// `f` while holding the lock of that context.
+    pub fn build_module(
+        &self,
+        name: &CStr,
+        f: impl FnOnce(&Module) -> Result<(), Error>,
+    ) -> Result<Owning<OrcThreadSafeModule<'_>>, Error> {
+        let slot = &self.slots[self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len()];
+        let m = slot.anchor.with_module_do(|anchor| {
+            let m = anchor.context().create_module(name);
+            f(&m)?;
+            Ok(m.into_raw())
+        })?;
+        Ok(slot.context.create_module(unsafe { Owning::from_raw(m) }))
+    }
+}
+
 impl OpaqueDrop for LLVMOrcOpaqueThreadSafeModule {
     unsafe fn drop_raw(ptr: *mut Self) {
         unsafe { LLVMOrcDisposeThreadSafeModule(ptr) }
@@ -715,9 +1640,9 @@ impl<'c> OrcThreadSafeModule<'c> {
         &self,
         f: LLVMOrcGenericIRModuleOperationFunction,
         ctx: *mut (),
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcThreadSafeModuleWithModuleDo(
+            OpaqueError::check(LLVMOrcThreadSafeModuleWithModuleDo(
                 self.as_raw(),
                 f,
                 ctx as _,
@@ -725,45 +1650,53 @@ impl<'c> OrcThreadSafeModule<'c> {
         }
     }
 
-    pub fn with_module_do<F: FnOnce(&Module) -> Result<R, Owning<Error>>, R>(
+    pub fn with_module_do<F: FnOnce(&Module) -> Result<R, Error>, R>(
         &self,
         f: F,
-    ) -> Result<R, Owning<Error>> {
-        struct Ctx<F: FnOnce(&Module) -> Result<R, Owning<Error>>, R> {
+    ) -> Result<R, Error> {
+        struct Ctx<F: FnOnce(&Module) -> Result<R, Error>, R> {
             f: Option<F>,
             r: Option<R>,
+            panic: PanicSlot,
         }
         let mut ctx = Ctx::<F, R> {
             f: Some(f),
             r: None,
+            panic: PanicSlot::default(),
         };
 
-        extern "C" fn operation<F: FnOnce(&Module) -> Result<R, Owning<Error>>, R>(
+        extern "C" fn operation<F: FnOnce(&Module) -> Result<R, Error>, R>(
             ctx: *mut c_void,
             m: *mut LLVMModule,
         ) -> *mut LLVMOpaqueError {
-            unsafe {
-                let ctx = &mut *(ctx as *mut Ctx<F, R>);
-                match ctx.f.take().unwrap()(Module::from_raw(m)) {
-                    Ok(r) => {
-                        ctx.r = Some(r);
-                        null_mut()
-                    }
-                    Err(e) => e.into_raw(),
+            let ctx = unsafe { &mut *(ctx as *mut Ctx<F, R>) };
+            // The operation runs before `with_module_do_raw` returns.
+            let f = ctx.f.take().unwrap();
+            let result = ctx.panic.catch(
+                || Err(Error::Failed("run module operation, it panicked".into())),
+                || f(unsafe { Module::from_raw(m) }),
+            );
+            match result {
+                Ok(r) => {
+                    ctx.r = Some(r);
+                    null_mut()
                 }
+                Err(e) => e.into_opaque().into_raw(),
             }
         }
 
-        self.with_module_do_raw(operation::<F, R>, &mut ctx as *mut _ as *mut _)?;
+        let result = self.with_module_do_raw(operation::<F, R>, &mut ctx as *mut _ as *mut _);
+        ctx.panic.resume();
+        result?;
         Ok(ctx.r.unwrap())
     }
 }
 
 impl OrcJitTargetMachineBuilder {
-    pub fn detect_host() -> Result<Owning<OrcJitTargetMachineBuilder>, Owning<Error>> {
+    pub fn detect_host() -> Result<Owning<OrcJitTargetMachineBuilder>, Error> {
         unsafe {
             let mut result = null_mut();
-            Error::check(LLVMOrcJITTargetMachineBuilderDetectHost(&mut result))?;
+            OpaqueError::check(LLVMOrcJITTargetMachineBuilderDetectHost(&mut result))?;
             Ok(Owning::from_raw(result))
         }
     }
@@ -788,10 +1721,9 @@ impl OrcJitTargetMachineBuilder {
         unsafe { CStr::from_ptr(LLVMOrcJITTargetMachineBuilderGetTargetTriple(self.as_raw())) }
     }
 
-    pub unsafe fn set_target_triple(&self, target_triple: &CStr) {
-        unsafe {
-            LLVMOrcJITTargetMachineBuilderSetTargetTriple(self.as_raw(), target_triple.as_ptr())
-        }
+    pub unsafe fn set_target_triple(&self, target_triple: impl AsRef<CStr>) {
+        let target_triple = target_triple.as_ref().as_ptr();
+        unsafe { LLVMOrcJITTargetMachineBuilderSetTargetTriple(self.as_raw(), target_triple) }
     }
 }
 
@@ -800,9 +1732,9 @@ impl OrcObjectLayer {
         &self,
         jd: &OrcJitDylib,
         obj_buffer: &MemoryBuffer,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcObjectLayerAddObjectFile(
+            OpaqueError::check(LLVMOrcObjectLayerAddObjectFile(
                 self.as_raw(),
                 jd.as_raw(),
                 obj_buffer.as_raw(),
@@ -814,9 +1746,9 @@ impl OrcObjectLayer {
         &self,
         rt: &OrcResourceTracker,
         obj_buffer: &MemoryBuffer,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMOrcObjectLayerAddObjectFileWithRT(
+            OpaqueError::check(LLVMOrcObjectLayerAddObjectFileWithRT(
                 self.as_raw(),
                 rt.as_raw(),
                 obj_buffer.as_raw(),
@@ -836,8 +1768,12 @@ impl OpaqueDrop for LLVMOrcOpaqueObjectLayer {
 }
 
 impl OrcIrTransformLayer {
-    pub fn emit(&self, mr: &OrcMaterializationResponsibility, tsm: &OrcThreadSafeModule) {
-        unsafe { LLVMOrcIRTransformLayerEmit(self.as_raw(), mr.as_raw(), tsm.as_raw()) }
+    pub fn emit(
+        &self,
+        mr: Owning<OrcMaterializationResponsibility>,
+        tsm: Owning<OrcThreadSafeModule>,
+    ) {
+        unsafe { LLVMOrcIRTransformLayerEmit(self.as_raw(), mr.into_raw(), tsm.into_raw()) }
     }
 
     pub fn set_transform_raw(
@@ -847,6 +1783,46 @@ impl OrcIrTransformLayer {
     ) {
         unsafe { LLVMOrcIRTransformLayerSetTransform(self.as_raw(), transform, ctx as _) }
     }
+
+    /// Set a transform run on every module the layer emits. It may modify the
+    /// module in place, or replace it. The transform is leaked, as the layer
+    /// never releases it.
+    pub fn set_transform<F>(&self, f: F)
+    where
+        F: Fn(
+                &mut Owning<OrcThreadSafeModule<'static>>,
+                &OrcMaterializationResponsibility,
+            ) -> Result<(), Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        extern "C" fn transform_raw<F>(
+            ctx: *mut c_void,
+            mod_in_out: *mut LLVMOrcThreadSafeModuleRef,
+            mr: LLVMOrcMaterializationResponsibilityRef,
+        ) -> LLVMErrorRef
+        where
+            F: Fn(
+                &mut Owning<OrcThreadSafeModule<'static>>,
+                &OrcMaterializationResponsibility,
+            ) -> Result<(), Error>,
+        {
+            unsafe {
+                // The module stays owned by the layer, unless replaced.
+                let mut tsm = ManuallyDrop::new(Owning::from_raw(*mod_in_out));
+                let result = catch_as_error("transform module", || {
+                    (*(ctx as *const F))(&mut tsm, OrcMaterializationResponsibility::from_raw(mr))
+                });
+                *mod_in_out = ManuallyDrop::into_inner(tsm).into_raw();
+                match result {
+                    Ok(()) => null_mut(),
+                    Err(e) => e.into_opaque().into_raw(),
+                }
+            }
+        }
+        self.set_transform_raw(transform_raw::<F>, Box::into_raw(Box::new(f)) as _)
+    }
 }
 
 impl OrcObjectTransformLayer {
@@ -857,15 +1833,42 @@ impl OrcObjectTransformLayer {
     ) {
         unsafe { LLVMOrcObjectTransformLayerSetTransform(self.as_raw(), transform, ctx as _) }
     }
+
+    /// Set a transform run on every object the layer emits. It may replace the
+    /// object buffer. The transform is leaked, as the layer never releases it.
+    pub fn set_transform<F>(&self, f: F)
+    where
+        F: Fn(&mut Owning<MemoryBuffer>) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        extern "C" fn transform_raw<F>(
+            ctx: *mut c_void,
+            obj_in_out: *mut LLVMMemoryBufferRef,
+        ) -> LLVMErrorRef
+        where
+            F: Fn(&mut Owning<MemoryBuffer>) -> Result<(), Error>,
+        {
+            unsafe {
+                // The buffer stays owned by the layer, unless replaced.
+                let mut obj = ManuallyDrop::new(Owning::from_raw(*obj_in_out));
+                let result = catch_as_error("transform object", || (*(ctx as *const F))(&mut obj));
+                *obj_in_out = ManuallyDrop::into_inner(obj).into_raw();
+                match result {
+                    Ok(()) => null_mut(),
+                    Err(e) => e.into_opaque().into_raw(),
+                }
+            }
+        }
+        self.set_transform_raw(transform_raw::<F>, Box::into_raw(Box::new(f)) as _)
+    }
 }
 
 impl OrcIndirectStubsManager {
     pub fn create_local_indirect_stubs_manager(
-        target_triple: &CStr,
+        target_triple: impl AsRef<CStr>,
     ) -> Owning<OrcIndirectStubsManager> {
         unsafe {
             Owning::from_raw(LLVMOrcCreateLocalIndirectStubsManager(
-                target_triple.as_ptr(),
+                target_triple.as_ref().as_ptr(),
             ))
         }
     }
@@ -904,10 +1907,10 @@ impl OrcDumpObjects {
     pub fn call_operator(
         &self,
         obj_buffers: Owning<MemoryBuffer>,
-    ) -> Result<Owning<MemoryBuffer>, Owning<Error>> {
+    ) -> Result<Owning<MemoryBuffer>, Error> {
         unsafe {
             let mut obj_buffers = obj_buffers.into_raw();
-            Error::check(LLVMOrcDumpObjects_CallOperator(
+            OpaqueError::check(LLVMOrcDumpObjects_CallOperator(
                 self.as_raw(),
                 &mut obj_buffers,
             ))?;
@@ -0,0 +1,317 @@
+//! Writers of the files `perf` reads to symbolize JIT-compiled code.
+//!
+//! A [`PerfMap`] only names address ranges, while a [`JitDump`] also records
+//! the code and its line table, for `perf inject --jit` to build ELF images
+//! `perf report` can annotate. Both are fed by hand, for instance with the
+//! addresses of `OrcLLJIT` lookups, as the C API cannot register a listener
+//! implemented in Rust.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+
+use crate::opaque::Opaque;
+use crate::{DIFile, DILocation};
+
+/// A `/tmp/perf-<pid>.map` file.
+pub struct PerfMap {
+    file: BufWriter<File>,
+}
+
+impl PerfMap {
+    /// Create the map of this process, or append to it if it exists.
+    pub fn create() -> io::Result<Self> {
+        Self::create_at(format!("/tmp/perf-{}.map", std::process::id()))
+    }
+
+    pub fn create_at(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Name the code in `address..address + size`. Lines are flushed as they
+    /// are written, as `perf` may read the map at any time.
+    pub fn write_symbol(&mut self, address: u64, size: u64, name: &str) -> io::Result<()> {
+        writeln!(self.file, "{address:x} {size:x} {name}")?;
+        self.file.flush()
+    }
+}
+
+/// A line of source code starting at an address, as recorded by a [`JitDump`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugEntry {
+    pub address: u64,
+    pub line: u32,
+    pub column: u32,
+    pub filename: String,
+}
+
+impl DebugEntry {
+    /// The entry for the code at `address` compiled from `loc`.
+    pub fn from_location(address: u64, loc: &DILocation) -> Self {
+        let filename = loc
+            .get_scope()
+            .get_file()
+            .try_cast::<DIFile>()
+            .map(|file| {
+                let directory = String::from_utf8_lossy(file.get_directory());
+                let filename = String::from_utf8_lossy(file.get_filename());
+                let mut path = PathBuf::from(&*directory);
+                path.push(&*filename);
+                path.to_string_lossy().into_owned()
+            })
+            .unwrap_or_default();
+        Self {
+            address,
+            line: loc.get_line(),
+            column: loc.get_column(),
+            filename,
+        }
+    }
+}
+
+const JITDUMP_MAGIC: u32 = 0x4A695444;
+const JITDUMP_VERSION: u32 = 1;
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_DEBUG_INFO: u32 = 2;
+const JIT_CODE_CLOSE: u32 = 3;
+
+#[cfg(target_arch = "x86")]
+const ELF_MACHINE: u32 = 3;
+#[cfg(target_arch = "arm")]
+const ELF_MACHINE: u32 = 40;
+#[cfg(target_arch = "x86_64")]
+const ELF_MACHINE: u32 = 62;
+#[cfg(target_arch = "aarch64")]
+const ELF_MACHINE: u32 = 183;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const ELF_MACHINE: u32 = 243;
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "arm",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+)))]
+const ELF_MACHINE: u32 = 0;
+
+/// The `perf record -k mono` clock.
+fn timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// A `jit-<pid>.dump` file, in the jitdump format of `perf`.
+///
+/// Record with `perf record -k mono`, then run `perf inject --jit` on the
+/// recording before `perf report`.
+pub struct JitDump {
+    file: BufWriter<File>,
+    /// The executable mapping of the file `perf record` finds the file by.
+    marker: *mut libc::c_void,
+    marker_len: usize,
+    code_index: u64,
+}
+
+impl JitDump {
+    /// Create the dump of this process in `dir`.
+    pub fn create(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let path = dir
+            .as_ref()
+            .join(format!("jit-{}.dump", std::process::id()));
+        // The marker mapping needs the file to be readable.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let marker_len = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        let marker = unsafe {
+            libc::mmap(
+                null_mut(),
+                marker_len,
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mut this = Self {
+            file: BufWriter::new(file),
+            marker,
+            marker_len,
+            code_index: 0,
+        };
+        let mut header = Vec::with_capacity(40);
+        header.extend(JITDUMP_MAGIC.to_ne_bytes());
+        header.extend(JITDUMP_VERSION.to_ne_bytes());
+        header.extend(40u32.to_ne_bytes());
+        header.extend(ELF_MACHINE.to_ne_bytes());
+        header.extend(0u32.to_ne_bytes());
+        header.extend(std::process::id().to_ne_bytes());
+        header.extend(timestamp().to_ne_bytes());
+        header.extend(0u64.to_ne_bytes());
+        this.file.write_all(&header)?;
+        this.file.flush()?;
+        Ok(this)
+    }
+
+    fn write_record(&mut self, id: u32, body: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(16 + body.len());
+        record.extend(id.to_ne_bytes());
+        record.extend((16 + body.len() as u32).to_ne_bytes());
+        record.extend(timestamp().to_ne_bytes());
+        record.extend(body);
+        self.file.write_all(&record)?;
+        self.file.flush()
+    }
+
+    /// Record the code of a function loaded at `address`, and the lines of
+    /// source code it was compiled from, if any.
+    pub fn write_code_load(
+        &mut self,
+        name: &str,
+        address: u64,
+        code: &[u8],
+        debug: &[DebugEntry],
+    ) -> io::Result<()> {
+        // The debug info of a function precedes its code.
+        if !debug.is_empty() {
+            let mut body = Vec::new();
+            body.extend(address.to_ne_bytes());
+            body.extend((debug.len() as u64).to_ne_bytes());
+            for entry in debug {
+                body.extend(entry.address.to_ne_bytes());
+                body.extend(entry.line.to_ne_bytes());
+                // The discriminator.
+                body.extend(0u32.to_ne_bytes());
+                body.extend(entry.filename.as_bytes());
+                body.push(0);
+            }
+            self.write_record(JIT_CODE_DEBUG_INFO, &body)?;
+        }
+
+        let mut body = Vec::with_capacity(40 + name.len() + 1 + code.len());
+        body.extend(std::process::id().to_ne_bytes());
+        body.extend((unsafe { libc::gettid() } as u32).to_ne_bytes());
+        body.extend(address.to_ne_bytes());
+        body.extend(address.to_ne_bytes());
+        body.extend((code.len() as u64).to_ne_bytes());
+        body.extend(self.code_index.to_ne_bytes());
+        body.extend(name.as_bytes());
+        body.push(0);
+        body.extend(code);
+        self.code_index += 1;
+        self.write_record(JIT_CODE_LOAD, &body)
+    }
+}
+
+impl Drop for JitDump {
+    fn drop(&mut self) {
+        let _ = self.write_record(JIT_CODE_CLOSE, &[]);
+        unsafe { libc::munmap(self.marker, self.marker_len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("llvm_quick-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_ne_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    static CODE: [u8; 4] = [0x90, 0x90, 0x90, 0xc3];
+
+    /// The code of a function `f`, compiled from line 3 of `/src/f.c`.
+    fn code_load(dump: &mut JitDump) {
+        let code = CODE.as_ptr() as u64;
+        let debug = [DebugEntry {
+            address: code,
+            line: 3,
+            column: 1,
+            filename: "/src/f.c".into(),
+        }];
+        dump.write_code_load("f", code, &CODE, &debug).unwrap();
+    }
+
+    #[test]
+    fn perf_map_lines() {
+        let path = temp_dir("perf-map").join("perf.map");
+        let _ = std::fs::remove_file(&path);
+        let mut map = PerfMap::create_at(&path).unwrap();
+        map.write_symbol(0x1000, 0x20, "first").unwrap();
+        map.write_symbol(0x2000, 0x8, "second").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "1000 20 first\n2000 8 second\n"
+        );
+    }
+
+    #[test]
+    fn jitdump_header_and_records() {
+        let dir = temp_dir("jitdump");
+        let mut dump = JitDump::create(&dir).unwrap();
+        code_load(&mut dump);
+        drop(dump);
+        let data = std::fs::read(dir.join(format!("jit-{}.dump", std::process::id()))).unwrap();
+
+        assert_eq!(u32_at(&data, 0), JITDUMP_MAGIC);
+        assert_eq!(u32_at(&data, 4), JITDUMP_VERSION);
+        assert_eq!(u32_at(&data, 8), 40);
+        assert_eq!(u32_at(&data, 12), ELF_MACHINE);
+        assert_eq!(u32_at(&data, 20), std::process::id());
+        assert_eq!(u64_at(&data, 32), 0);
+
+        let code = CODE.as_ptr() as u64;
+        // The line of `f` precedes its code.
+        let debug = 40;
+        assert_eq!(u32_at(&data, debug), JIT_CODE_DEBUG_INFO);
+        let debug_size = u32_at(&data, debug + 4) as usize;
+        assert_eq!(debug_size, 16 + 16 + 16 + "/src/f.c".len() + 1);
+        assert_eq!(u64_at(&data, debug + 16), code);
+        assert_eq!(u64_at(&data, debug + 24), 1);
+        assert_eq!(u64_at(&data, debug + 32), code);
+        assert_eq!(u32_at(&data, debug + 40), 3);
+        assert_eq!(&data[debug + 48..debug + debug_size], b"/src/f.c\0");
+
+        let load = debug + debug_size;
+        assert_eq!(u32_at(&data, load), JIT_CODE_LOAD);
+        let load_size = u32_at(&data, load + 4) as usize;
+        assert_eq!(load_size, 16 + 40 + 2 + 4);
+        assert_eq!(u32_at(&data, load + 16), std::process::id());
+        assert_eq!(u64_at(&data, load + 24), code);
+        assert_eq!(u64_at(&data, load + 32), code);
+        assert_eq!(u64_at(&data, load + 40), 4);
+        assert_eq!(u64_at(&data, load + 48), 0);
+        assert_eq!(&data[load + 56..load + load_size], b"f\0\x90\x90\x90\xc3");
+
+        let close = load + load_size;
+        assert_eq!(u32_at(&data, close), JIT_CODE_CLOSE);
+        assert_eq!(u32_at(&data, close + 4), 16);
+        assert_eq!(data.len(), close + 16);
+    }
+}
@@ -2,11 +2,17 @@ use std::ffi::CStr;
 
 use llvm_sys::support::*;
 
+use crate::Error;
+
 /// This function permanently loads the dynamic library at the given path.
 ///
 /// It is safe to call this function multiple times for the same library.
-pub fn load_library_permanently(filename: &CStr) -> bool {
-    unsafe { LLVMLoadLibraryPermanently(filename.as_ptr()) != 0 }
+pub fn load_library_permanently(filename: &CStr) -> Result<(), Error> {
+    if unsafe { LLVMLoadLibraryPermanently(filename.as_ptr()) } != 0 {
+        Err(Error::Failed(format!("load library {filename:?}")))
+    } else {
+        Ok(())
+    }
 }
 
 /// This function parses the given arguments using the LLVM command line parser.
@@ -76,16 +76,40 @@ impl TargetData {
         }
     }
 
-    pub fn get_size_of_type_in_bits<T: TypeTag>(&self, ty: &Type<T>) -> u64 {
-        unsafe { LLVMSizeOfTypeInBits(self.as_raw(), ty.as_raw()) }
+    /// Get the size of a type in bits, a multiple of `vscale` for scalable vectors.
+    pub fn get_size_of_type_in_bits<T: TypeTag>(&self, ty: &Type<T>) -> TypeSize {
+        match ty.to_any().try_cast::<Type<scalable_vector_any>>() {
+            Some(ty) => TypeSize {
+                min: self.get_size_of_type_in_bits(ty.element_type()).min * ty.get_size() as u64,
+                scalable: true,
+            },
+            None => TypeSize::fixed(unsafe { LLVMSizeOfTypeInBits(self.as_raw(), ty.as_raw()) }),
+        }
     }
 
-    pub fn get_store_size_of_type<T: TypeTag>(&self, ty: &Type<T>) -> u64 {
-        unsafe { LLVMStoreSizeOfType(self.as_raw(), ty.as_raw()) }
+    /// Get the store size of a type in bytes, a multiple of `vscale` for scalable vectors.
+    pub fn get_store_size_of_type<T: TypeTag>(&self, ty: &Type<T>) -> TypeSize {
+        let bits = self.get_size_of_type_in_bits(ty);
+        if !bits.scalable {
+            return TypeSize::fixed(unsafe { LLVMStoreSizeOfType(self.as_raw(), ty.as_raw()) });
+        }
+        TypeSize {
+            min: bits.min.div_ceil(8),
+            scalable: true,
+        }
     }
 
-    pub fn get_abi_size_of_type<T: TypeTag>(&self, ty: &Type<T>) -> u64 {
-        unsafe { LLVMABISizeOfType(self.as_raw(), ty.as_raw()) }
+    /// Get the allocation size of a type in bytes, a multiple of `vscale` for scalable vectors.
+    pub fn get_abi_size_of_type<T: TypeTag>(&self, ty: &Type<T>) -> TypeSize {
+        let store = self.get_store_size_of_type(ty);
+        if !store.scalable {
+            return TypeSize::fixed(unsafe { LLVMABISizeOfType(self.as_raw(), ty.as_raw()) });
+        }
+        let align = self.get_abi_alignment_of_type(ty) as u64;
+        TypeSize {
+            min: store.min.next_multiple_of(align),
+            scalable: true,
+        }
     }
 
     pub fn get_abi_alignment_of_type<T: TypeTag>(&self, ty: &Type<T>) -> u32 {
@@ -113,6 +137,31 @@ impl TargetData {
     }
 }
 
+/// The size of a type, which is a multiple of `vscale` if it is scalable.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TypeSize {
+    pub min: u64,
+    pub scalable: bool,
+}
+
+impl TypeSize {
+    pub fn fixed(size: u64) -> Self {
+        Self {
+            min: size,
+            scalable: false,
+        }
+    }
+
+    /// Get the size for a known `vscale`.
+    pub fn get(&self, vscale: u64) -> u64 {
+        if self.scalable {
+            self.min * vscale
+        } else {
+            self.min
+        }
+    }
+}
+
 impl OpaqueDrop for LLVMOpaqueTargetData {
     unsafe fn drop_raw(ptr: *mut Self) {
         unsafe { LLVMDisposeTargetData(ptr) }
@@ -143,18 +192,34 @@ pub fn initialize_all_disassemblers() {
     unsafe { LLVM_InitializeAllDisassemblers() }
 }
 
-pub fn initialize_native_target() -> bool {
-    unsafe { LLVM_InitializeNativeTarget() != 0 }
+pub fn initialize_native_target() -> Result<(), Error> {
+    if unsafe { LLVM_InitializeNativeTarget() } != 0 {
+        Err(Error::Failed("initialize native target".into()))
+    } else {
+        Ok(())
+    }
 }
 
-pub fn initialize_native_asm_parser() -> bool {
-    unsafe { LLVM_InitializeNativeAsmParser() != 0 }
+pub fn initialize_native_asm_parser() -> Result<(), Error> {
+    if unsafe { LLVM_InitializeNativeAsmParser() } != 0 {
+        Err(Error::Failed("initialize native asm parser".into()))
+    } else {
+        Ok(())
+    }
 }
 
-pub fn initialize_native_asm_printer() -> bool {
-    unsafe { LLVM_InitializeNativeAsmPrinter() != 0 }
+pub fn initialize_native_asm_printer() -> Result<(), Error> {
+    if unsafe { LLVM_InitializeNativeAsmPrinter() } != 0 {
+        Err(Error::Failed("initialize native asm printer".into()))
+    } else {
+        Ok(())
+    }
 }
 
-pub fn initialize_native_disassembler() -> bool {
-    unsafe { LLVM_InitializeNativeDisassembler() != 0 }
+pub fn initialize_native_disassembler() -> Result<(), Error> {
+    if unsafe { LLVM_InitializeNativeDisassembler() } != 0 {
+        Err(Error::Failed("initialize native disassembler".into()))
+    } else {
+        Ok(())
+    }
 }
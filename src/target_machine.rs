@@ -1,11 +1,14 @@
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::fmt::{Display, Formatter};
 use std::ptr::null_mut;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
 use llvm_sys::target_machine::*;
 
 use crate::core::Message;
 use crate::owning::{OpaqueDrop, Owning};
-use crate::target::TargetData;
+use crate::target::{initialize_native_asm_printer, initialize_native_target, TargetData};
 use crate::*;
 
 #[repr(transparent)]
@@ -53,12 +56,12 @@ impl Target {
         unsafe { Self::from_ptr(LLVMGetTargetFromName(name.as_ptr())) }
     }
 
-    pub fn from_triple(triple: &CStr) -> Result<&'static Self, Message> {
+    pub fn from_triple(triple: impl AsRef<CStr>) -> Result<&'static Self, Error> {
         unsafe {
             let mut ptr = null_mut();
             let mut err = null_mut();
-            if LLVMGetTargetFromTriple(triple.as_ptr(), &mut ptr, &mut err) != 0 {
-                return Err(Message::from_raw(err));
+            if LLVMGetTargetFromTriple(triple.as_ref().as_ptr(), &mut ptr, &mut err) != 0 {
+                return Err(Message::from_raw(err).into());
             }
             Ok(Self::from_raw(ptr))
         }
@@ -126,13 +129,13 @@ impl TargetMachineOptions {
 impl Target {
     pub fn create_target_machine_with_options(
         &self,
-        triple: &CStr,
+        triple: impl AsRef<CStr>,
         options: &TargetMachineOptions,
     ) -> Owning<TargetMachine> {
         unsafe {
             Owning::from_raw(LLVMCreateTargetMachineWithOptions(
                 self.as_raw(),
-                triple.as_ptr(),
+                triple.as_ref().as_ptr(),
                 options.as_raw(),
             ))
         }
@@ -140,7 +143,7 @@ impl Target {
 
     pub fn create_target_machine(
         &self,
-        triple: &CStr,
+        triple: impl AsRef<CStr>,
         cpu: &CStr,
         features: &CStr,
         level: LLVMCodeGenOptLevel,
@@ -150,7 +153,7 @@ impl Target {
         unsafe {
             Owning::from_raw(LLVMCreateTargetMachine(
                 self.as_raw(),
-                triple.as_ptr(),
+                triple.as_ref().as_ptr(),
                 cpu.as_ptr(),
                 features.as_ptr(),
                 level,
@@ -161,6 +164,52 @@ impl Target {
     }
 }
 
+impl TargetMachine {
+    /// Create a target machine for the host CPU and its features, initializing
+    /// the native target first if needed.
+    pub fn host(
+        level: LLVMCodeGenOptLevel,
+        reloc: LLVMRelocMode,
+        code_model: LLVMCodeModel,
+    ) -> Result<Owning<Self>, Error> {
+        Self::host_with_features(&CpuFeatures::host()?, level, reloc, code_model)
+    }
+
+    /// Like [`TargetMachine::host`], with the given features instead of the
+    /// features of the host CPU.
+    pub fn host_with_features(
+        features: &CpuFeatures,
+        level: LLVMCodeGenOptLevel,
+        reloc: LLVMRelocMode,
+        code_model: LLVMCodeModel,
+    ) -> Result<Owning<Self>, Error> {
+        static INITIALIZED: OnceLock<Result<(), Error>> = OnceLock::new();
+        INITIALIZED
+            .get_or_init(|| {
+                initialize_native_target()?;
+                initialize_native_asm_printer()
+            })
+            .clone()?;
+        let triple = get_default_target_triple();
+        let target = Target::from_triple(&triple)?;
+        let features = CString::new(features.to_string()).map_err(|_| {
+            Error::Message(format!("pass CPU features {features}, they contain a nul"))
+        })?;
+        unsafe {
+            Owning::from_ptr(LLVMCreateTargetMachine(
+                target.as_raw(),
+                triple.as_ptr(),
+                get_host_cpu_name().as_ptr(),
+                features.as_ptr(),
+                level,
+                reloc,
+                code_model,
+            ))
+        }
+        .ok_or_else(|| Error::Failed(format!("create a target machine for {triple:?}")))
+    }
+}
+
 impl OpaqueDrop for LLVMOpaqueTargetMachine {
     unsafe fn drop_raw(ptr: *mut Self) {
         unsafe { LLVMDisposeTargetMachine(ptr) }
@@ -213,7 +262,7 @@ impl TargetMachine {
         module: &Module,
         filename: &CStr,
         codegen: LLVMCodeGenFileType,
-    ) -> Result<(), Message> {
+    ) -> Result<(), Error> {
         unsafe {
             let mut err = null_mut();
             if LLVMTargetMachineEmitToFile(
@@ -224,7 +273,7 @@ impl TargetMachine {
                 &mut err,
             ) != 0
             {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
             Ok(())
         }
@@ -234,7 +283,7 @@ impl TargetMachine {
         &self,
         module: &Module,
         codegen: LLVMCodeGenFileType,
-    ) -> Result<Owning<MemoryBuffer>, Message> {
+    ) -> Result<Owning<MemoryBuffer>, Error> {
         unsafe {
             let mut err = null_mut();
             let mut mem = null_mut();
@@ -246,19 +295,185 @@ impl TargetMachine {
                 &mut mem,
             ) != 0
             {
-                return Err(Message::from_raw(err));
+                return Err(Message::from_raw(err).into());
             }
             Ok(Owning::from_raw(mem))
         }
     }
+
+    /// Compile `module` to an object file in memory.
+    pub fn emit_object(&self, module: &Module) -> Result<Vec<u8>, Error> {
+        let buffer = self.emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMObjectFile)?;
+        Ok(buffer.as_bytes().to_vec())
+    }
+
+    /// Compile `module` to assembly in memory.
+    pub fn emit_assembly(&self, module: &Module) -> Result<String, Error> {
+        let buffer = self.emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMAssemblyFile)?;
+        Ok(String::from_utf8_lossy(buffer.as_bytes()).into_owned())
+    }
 }
 
 pub fn get_default_target_triple() -> Message {
     unsafe { Message::from_raw(LLVMGetDefaultTargetTriple()) }
 }
 
-pub fn normalize_target_triple(triple: &CStr) -> Message {
-    unsafe { Message::from_raw(LLVMNormalizeTargetTriple(triple.as_ptr())) }
+pub fn normalize_target_triple(triple: impl AsRef<CStr>) -> Message {
+    unsafe { Message::from_raw(LLVMNormalizeTargetTriple(triple.as_ref().as_ptr())) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectFormat {
+    Coff,
+    DxContainer,
+    Elf,
+    Goff,
+    MachO,
+    SpirV,
+    Wasm,
+    XCoff,
+}
+
+/// A normalized target triple, `arch-vendor-os[-environment]`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Triple {
+    string: CString,
+    arch: String,
+    vendor: String,
+    os: String,
+    environment: String,
+}
+
+impl Triple {
+    /// The triple LLVM generates code for by default.
+    pub fn host() -> Self {
+        get_default_target_triple()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    pub fn new(arch: &str, vendor: &str, os: &str, environment: &str) -> Result<Self, Error> {
+        let triple = if environment.is_empty() {
+            format!("{arch}-{vendor}-{os}")
+        } else {
+            format!("{arch}-{vendor}-{os}-{environment}")
+        };
+        triple.parse()
+    }
+
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    pub fn vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    /// The environment, possibly followed by an object format, or an empty
+    /// string.
+    pub fn environment(&self) -> &str {
+        &self.environment
+    }
+
+    /// The object format named by the environment, or the default one of the
+    /// target, as chosen by LLVM.
+    pub fn object_format(&self) -> ObjectFormat {
+        let explicit = [
+            ("coff", ObjectFormat::Coff),
+            ("dxcontainer", ObjectFormat::DxContainer),
+            ("elf", ObjectFormat::Elf),
+            ("goff", ObjectFormat::Goff),
+            ("macho", ObjectFormat::MachO),
+            ("spirv", ObjectFormat::SpirV),
+            ("wasm", ObjectFormat::Wasm),
+            ("xcoff", ObjectFormat::XCoff),
+        ];
+        if let Some(&(_, format)) = explicit
+            .iter()
+            .find(|(suffix, _)| self.environment.ends_with(suffix))
+        {
+            return format;
+        }
+        let arch = self.arch.as_str();
+        let os = self.os.as_str();
+        if arch.starts_with("wasm") {
+            ObjectFormat::Wasm
+        } else if arch.starts_with("spirv") {
+            ObjectFormat::SpirV
+        } else if arch == "dxil" {
+            ObjectFormat::DxContainer
+        } else if [
+            "darwin",
+            "macos",
+            "ios",
+            "tvos",
+            "watchos",
+            "xros",
+            "driverkit",
+        ]
+        .iter()
+        .any(|darwin| os.starts_with(darwin))
+        {
+            ObjectFormat::MachO
+        } else if os.starts_with("windows") || os.starts_with("uefi") {
+            ObjectFormat::Coff
+        } else if os.starts_with("aix") {
+            ObjectFormat::XCoff
+        } else if os.starts_with("zos") {
+            ObjectFormat::Goff
+        } else {
+            ObjectFormat::Elf
+        }
+    }
+
+    pub fn as_c_str(&self) -> &CStr {
+        &self.string
+    }
+}
+
+impl FromStr for Triple {
+    type Err = Error;
+
+    /// Parse and normalize a triple, filling in missing components with
+    /// `unknown`. Fails only if `s` contains a nul byte.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let triple = CString::new(s).map_err(|e| Error::Message(e.to_string()))?;
+        let normalized = normalize_target_triple(&*triple);
+        let normalized = normalized.to_string_lossy();
+        let mut components = normalized.splitn(4, '-').map(str::to_owned);
+        let mut next = || components.next().unwrap_or_else(|| "unknown".to_owned());
+        let (arch, vendor, os) = (next(), next(), next());
+        let environment = components.next().unwrap_or_default();
+        let mut string = format!("{arch}-{vendor}-{os}");
+        if !environment.is_empty() {
+            string = format!("{string}-{environment}");
+        }
+        Ok(Self {
+            string: CString::new(string).unwrap(),
+            arch,
+            vendor,
+            os,
+            environment,
+        })
+    }
+}
+
+impl Display for Triple {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.string.to_string_lossy())
+    }
+}
+
+impl AsRef<CStr> for Triple {
+    fn as_ref(&self) -> &CStr {
+        &self.string
+    }
 }
 
 pub fn get_host_cpu_name() -> Message {
@@ -269,8 +484,132 @@ pub fn get_host_cpu_features() -> Message {
     unsafe { Message::from_raw(LLVMGetHostCPUFeatures()) }
 }
 
+/// A set of CPU features, as the `+feature,-feature` strings of
+/// [`get_host_cpu_features`] and [`Target::create_target_machine`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuFeatures {
+    /// In order, as the last mention of a feature wins.
+    features: Vec<(String, bool)>,
+}
+
+impl CpuFeatures {
+    /// The features of the host CPU.
+    pub fn host() -> Result<Self, Error> {
+        let features = get_host_cpu_features();
+        features
+            .to_str()
+            .map_err(|_| {
+                Error::Message(format!(
+                    "read host CPU features {features:?}, they are not UTF-8"
+                ))
+            })?
+            .parse()
+    }
+
+    /// Whether `feature` is enabled, or disabled, or left to the default of
+    /// the CPU.
+    pub fn get(&self, feature: &str) -> Option<bool> {
+        self.features
+            .iter()
+            .find(|(name, _)| name == feature)
+            .map(|&(_, enabled)| enabled)
+    }
+
+    pub fn is_enabled(&self, feature: &str) -> bool {
+        self.get(feature) == Some(true)
+    }
+
+    pub fn enable(&mut self, feature: &str) {
+        self.set(feature, true);
+    }
+
+    pub fn disable(&mut self, feature: &str) {
+        self.set(feature, false);
+    }
+
+    pub fn set(&mut self, feature: &str, enabled: bool) {
+        match self.features.iter_mut().find(|(name, _)| name == feature) {
+            Some(entry) => entry.1 = enabled,
+            None => self.features.push((feature.to_owned(), enabled)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.features
+            .iter()
+            .map(|(name, enabled)| (name.as_str(), *enabled))
+    }
+}
+
+impl FromStr for CpuFeatures {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut features = Self::default();
+        for feature in s.split(',').filter(|feature| !feature.is_empty()) {
+            if let Some(name) = feature.strip_prefix('+').filter(|name| !name.is_empty()) {
+                features.enable(name);
+            } else if let Some(name) = feature.strip_prefix('-').filter(|name| !name.is_empty()) {
+                features.disable(name);
+            } else {
+                return Err(Error::Message(format!("invalid CPU feature `{feature}`")));
+            }
+        }
+        Ok(features)
+    }
+}
+
+impl Display for CpuFeatures {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, enabled)) in self.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}{name}", if enabled { '+' } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
 impl PassManager {
     pub fn add_analysis_passes(&self, v: &TargetMachine) {
         unsafe { LLVMAddAnalysisPasses(v.as_raw(), self.as_raw()) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_features_round_trip() {
+        let features = "+sse2,-avx512f,+crc".parse::<CpuFeatures>().unwrap();
+        assert_eq!(features.get("sse2"), Some(true));
+        assert_eq!(features.get("avx512f"), Some(false));
+        assert_eq!(features.get("neon"), None);
+        assert!(features.is_enabled("crc"));
+        assert_eq!(features.to_string(), "+sse2,-avx512f,+crc");
+        assert_eq!(
+            features.to_string().parse::<CpuFeatures>().unwrap(),
+            features
+        );
+    }
+
+    #[test]
+    fn last_cpu_feature_mention_wins() {
+        let mut features = "+avx,,-avx,+fma".parse::<CpuFeatures>().unwrap();
+        assert_eq!(features.to_string(), "-avx,+fma");
+        features.enable("avx");
+        features.disable("sse4.2");
+        assert_eq!(features.to_string(), "+avx,+fma,-sse4.2");
+        assert_eq!("".parse::<CpuFeatures>().unwrap(), CpuFeatures::default());
+        assert_eq!(CpuFeatures::default().to_string(), "");
+    }
+
+    #[test]
+    fn invalid_cpu_features() {
+        for invalid in ["avx", "+", "-", "+avx,fma"] {
+            assert!(invalid.parse::<CpuFeatures>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::ffi::CStr;
 
 use llvm_sys::transforms::pass_builder::*;
 
-use crate::error::Error;
+use crate::error::{Error, OpaqueError};
 use crate::owning::{OpaqueDrop, Owning};
 use crate::target_machine::TargetMachine;
 use crate::*;
@@ -22,9 +22,9 @@ impl<'c> Module<'c> {
         passes: &CStr,
         target_machine: &TargetMachine,
         options: &PassBuilderOptions,
-    ) -> Result<(), Owning<Error>> {
+    ) -> Result<(), Error> {
         unsafe {
-            Error::check(LLVMRunPasses(
+            OpaqueError::check(LLVMRunPasses(
                 self.as_raw(),
                 passes.as_ptr(),
                 target_machine.as_raw(),
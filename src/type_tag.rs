@@ -2,11 +2,13 @@
 #![allow(non_snake_case)]
 
 use std::borrow::{Borrow, BorrowMut};
+use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 
 use llvm_sys::*;
 
+use crate::core::types::other::parse_target_ext_type;
 use crate::opaque::Opaque;
 use crate::{Argument, Type, Value};
 
@@ -43,6 +45,8 @@ impl TypeTag for void {
     }
 }
 
+pub trait StructTypeTag: TypeTag {}
+
 #[derive(Copy, Clone)]
 pub struct struct_any {}
 
@@ -52,6 +56,39 @@ impl TypeTag for struct_any {
     }
 }
 
+impl StructTypeTag for struct_any {}
+
+/// A literal struct whose element types are known.
+#[derive(Copy, Clone)]
+pub struct structure<Elements: TagTuple, const PACKED: bool = false> {
+    marker: PhantomData<fn(Elements) -> Elements>,
+}
+
+impl<Elements: TagTuple, const PACKED: bool> TypeTag for structure<Elements, PACKED> {
+    fn type_cast(ty: &Type<any>) -> Option<&Type<Self>> {
+        let ty = struct_any::type_cast(ty)?;
+        if ty.is_opaque_struct() || ty.is_packed_struct() != PACKED {
+            return None;
+        }
+        if ty.count_struct_element_types() as usize != Elements::COUNT {
+            return None;
+        }
+        unsafe {
+            let mut array =
+                MaybeUninit::<<Elements::Types<'_> as Tuple>::Array<Option<&Type<any>>>>::zeroed()
+                    .assume_init();
+            ty.get_struct_element_types(array.as_mut());
+            Elements::Types::try_from_array_any(std::mem::transmute::<
+                &[Option<&Type<any>>],
+                &[&Type<any>],
+            >(array.as_ref()))?;
+            Some(ty.cast_unchecked())
+        }
+    }
+}
+
+impl<Elements: TagTuple, const PACKED: bool> StructTypeTag for structure<Elements, PACKED> {}
+
 #[derive(Copy, Clone)]
 pub struct label {}
 
@@ -97,6 +134,8 @@ impl TypeTag for token {
     }
 }
 
+pub trait TargetExtTypeTag: TypeTag {}
+
 #[derive(Copy, Clone)]
 pub struct target_ext_any {}
 
@@ -106,6 +145,55 @@ impl TypeTag for target_ext_any {
     }
 }
 
+impl TargetExtTypeTag for target_ext_any {}
+
+/// Name a target extension type for use with [`target_ext`].
+pub trait TargetExtName {
+    const NAME: &'static CStr;
+}
+
+/// A target extension type with a known name, like `target("spirv.Image", ...)`.
+#[derive(Copy, Clone)]
+pub struct target_ext<N: TargetExtName> {
+    marker: PhantomData<fn(N) -> N>,
+}
+
+impl<N: TargetExtName> TypeTag for target_ext<N> {
+    fn type_cast(ty: &Type<any>) -> Option<&Type<Self>> {
+        let ty = target_ext_any::type_cast(ty)?;
+        // Print and parse once, without panicking on an unexpected form.
+        let (name, _) = parse_target_ext_type(ty.print_to_string().to_bytes())?;
+        if name.as_c_str() == N::NAME {
+            Some(unsafe { ty.cast_unchecked() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<N: TargetExtName> TargetExtTypeTag for target_ext<N> {}
+
+macro_rules! target_ext_name {
+    ($($name:ident = $value:literal;)*) => {
+        $(
+            #[derive(Copy, Clone)]
+            pub struct $name {}
+
+            impl TargetExtName for $name {
+                const NAME: &'static CStr = $value;
+            }
+        )*
+    };
+}
+
+target_ext_name! {
+    aarch64_svcount = c"aarch64.svcount";
+    spirv_image = c"spirv.Image";
+    spirv_sampler = c"spirv.Sampler";
+    spirv_sampled_image = c"spirv.SampledImage";
+    spirv_event = c"spirv.Event";
+}
+
 pub trait SequentialTypeTag: TypeTag {
     type ElementType: TypeTag;
 }
@@ -160,6 +248,10 @@ impl<T: TypeTag, const N: u64> ArrayTypeTag for array<T, N> {}
 
 pub trait VectorTypeTag: SequentialTypeTag {}
 
+pub trait FixedVectorTypeTag: VectorTypeTag {}
+
+pub trait ScalableVectorTypeTag: VectorTypeTag {}
+
 pub type vector_any = vector_any_len<any>;
 
 #[derive(Copy, Clone)]
@@ -184,6 +276,7 @@ impl<T: TypeTag> SequentialTypeTag for vector_any_len<T> {
     type ElementType = T;
 }
 impl<T: TypeTag> VectorTypeTag for vector_any_len<T> {}
+impl<T: TypeTag> FixedVectorTypeTag for vector_any_len<T> {}
 
 #[derive(Copy, Clone)]
 pub struct vector<T: TypeTag, const N: u32> {
@@ -205,6 +298,60 @@ impl<T: TypeTag, const N: u32> SequentialTypeTag for vector<T, N> {
     type ElementType = T;
 }
 impl<T: TypeTag, const N: u32> VectorTypeTag for vector<T, N> {}
+impl<T: TypeTag, const N: u32> FixedVectorTypeTag for vector<T, N> {}
+
+pub type scalable_vector_any = scalable_vector_any_len<any>;
+
+/// A vector of `vscale * N` elements, for some `N`.
+#[derive(Copy, Clone)]
+pub struct scalable_vector_any_len<T: TypeTag> {
+    marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T: TypeTag> TypeTag for scalable_vector_any_len<T> {
+    fn type_cast(ty: &Type<any>) -> Option<&Type<Self>> {
+        unsafe {
+            let ty = type_check_kind::<scalable_vector_any>(
+                ty,
+                LLVMTypeKind::LLVMScalableVectorTypeKind,
+            )?;
+            if ty.element_type().try_cast::<Type<T>>().is_some() {
+                Some(ty.cast_unchecked())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl<T: TypeTag> SequentialTypeTag for scalable_vector_any_len<T> {
+    type ElementType = T;
+}
+impl<T: TypeTag> VectorTypeTag for scalable_vector_any_len<T> {}
+impl<T: TypeTag> ScalableVectorTypeTag for scalable_vector_any_len<T> {}
+
+/// A vector of `vscale * MIN` elements.
+#[derive(Copy, Clone)]
+pub struct scalable_vector<T: TypeTag, const MIN: u32> {
+    marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T: TypeTag, const MIN: u32> TypeTag for scalable_vector<T, MIN> {
+    fn type_cast(ty: &Type<any>) -> Option<&Type<Self>> {
+        let ty = scalable_vector_any_len::<T>::type_cast(ty)?;
+        if ty.get_size() == MIN {
+            Some(unsafe { ty.cast_unchecked() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: TypeTag, const MIN: u32> SequentialTypeTag for scalable_vector<T, MIN> {
+    type ElementType = T;
+}
+impl<T: TypeTag, const MIN: u32> VectorTypeTag for scalable_vector<T, MIN> {}
+impl<T: TypeTag, const MIN: u32> ScalableVectorTypeTag for scalable_vector<T, MIN> {}
 
 pub trait FloatTypeTag: TypeTag {}
 
@@ -430,6 +577,13 @@ impl InstanceTypeTag for ppc_fp128 {}
 impl InstanceTypeTag for bfloat {}
 impl<const N: u32> InstanceTypeTag for int<N> {}
 impl<const ADDRESS_SPACE: u32> InstanceTypeTag for ptr<ADDRESS_SPACE> {}
+impl<T: InstanceTypeTag, const N: u32> InstanceTypeTag for vector<T, N> {}
+impl<N: TargetExtName> InstanceTypeTag for target_ext<N> {}
+impl<T: InstanceTypeTag, const MIN: u32> InstanceTypeTag for scalable_vector<T, MIN> {}
+impl<Elements: InstanceTagTuple, const PACKED: bool> InstanceTypeTag
+    for structure<Elements, PACKED>
+{
+}
 impl<Args: InstanceTagTuple, Output: InstanceTypeTag, const VAR: bool> InstanceTypeTag
     for fun<Args, Output, VAR>
 {
@@ -437,6 +591,8 @@ impl<Args: InstanceTagTuple, Output: InstanceTypeTag, const VAR: bool> InstanceT
 
 pub trait IntMathTypeTag: InstanceTypeTag {}
 impl<const N: u32> IntMathTypeTag for int<N> {}
+impl<const N: u32, const M: u32> IntMathTypeTag for vector<int<N>, M> {}
+impl<const N: u32, const MIN: u32> IntMathTypeTag for scalable_vector<int<N>, MIN> {}
 
 pub trait FloatMathTypeTag: InstanceTypeTag {}
 impl FloatMathTypeTag for half {}
@@ -446,9 +602,24 @@ impl FloatMathTypeTag for x86_fp80 {}
 impl FloatMathTypeTag for fp128 {}
 impl FloatMathTypeTag for ppc_fp128 {}
 impl FloatMathTypeTag for bfloat {}
+impl<const N: u32> FloatMathTypeTag for vector<half, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<float, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<double, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<x86_fp80, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<fp128, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<ppc_fp128, N> {}
+impl<const N: u32> FloatMathTypeTag for vector<bfloat, N> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<half, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<float, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<double, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<x86_fp80, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<fp128, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<ppc_fp128, MIN> {}
+impl<const MIN: u32> FloatMathTypeTag for scalable_vector<bfloat, MIN> {}
 
 pub trait ElementTypeTag: TypeTag {}
 impl ElementTypeTag for struct_any {}
+impl<Elements: TagTuple, const PACKED: bool> ElementTypeTag for structure<Elements, PACKED> {}
 impl<T: TypeTag, const N: u64> ElementTypeTag for array<T, N> {}
 impl<T: TypeTag> ElementTypeTag for array_any_len<T> {}
 
@@ -1 +1,89 @@
+use std::ffi::CString;
 
+use llvm_quick::core::contexts::DiagnosticCollector;
+use llvm_quick::orc2::lljit::OrcLLJIT;
+use llvm_quick::orc2::OrcThreadSafeContextPool;
+use llvm_quick::target::{initialize_native_asm_printer, initialize_native_target};
+use llvm_quick::Context;
+
+const THREADS: u64 = 8;
+const MODULES_PER_THREAD: u64 = 50;
+
+fn function_name(n: u64) -> CString {
+    CString::new(format!("constant_{n}")).unwrap()
+}
+
+#[test]
+fn orc_concurrent_modules() {
+    initialize_native_target().unwrap();
+    initialize_native_asm_printer().unwrap();
+    let jit = OrcLLJIT::create(None).unwrap();
+    let pool = OrcThreadSafeContextPool::new(4);
+    let handle = jit.handle();
+
+    std::thread::scope(|s| {
+        for t in 0..THREADS {
+            let pool = &pool;
+            s.spawn(move || {
+                for i in 0..MODULES_PER_THREAD {
+                    let n = t * MODULES_PER_THREAD + i;
+                    let name = function_name(n);
+                    let tsm = pool
+                        .build_module(&name, |m| {
+                            let context = m.context();
+                            let i64_type = context.i64_type();
+                            let function = m.add_function(&name, i64_type.fun(()));
+                            let builder = context.create_builder();
+                            builder.position_at_end(context.append_basic_block(function, c"entry"));
+                            builder.return_value(i64_type.const_int(n, false));
+                            Ok(())
+                        })
+                        .unwrap();
+                    handle.add_llvm_ir_module(tsm).unwrap();
+                }
+            });
+        }
+    });
+
+    std::thread::scope(|s| {
+        for t in 0..THREADS {
+            s.spawn(move || {
+                for i in 0..MODULES_PER_THREAD {
+                    let n = t * MODULES_PER_THREAD + i;
+                    let addr = handle.lookup(&function_name(n)).unwrap();
+                    let f = unsafe { std::mem::transmute::<u64, extern "C" fn() -> u64>(addr) };
+                    assert_eq!(f(), n);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn orc_lookup_missing_symbol() {
+    initialize_native_target().unwrap();
+    initialize_native_asm_printer().unwrap();
+    let jit = OrcLLJIT::create(None).unwrap();
+    assert!(jit.handle().lookup(c"missing").is_err());
+}
+
+#[test]
+fn diagnostic_handler_is_restored() {
+    let context = Context::create();
+    let previous = context.get_diagnostic_context();
+    let collector = DiagnosticCollector::new();
+    collector.collect(&context, || {
+        assert_ne!(context.get_diagnostic_context(), previous);
+    });
+    assert_eq!(context.get_diagnostic_context(), previous);
+}
+
+#[test]
+fn diagnostic_handler_guard_restores_on_drop() {
+    let context = Context::create();
+    let previous = context.get_diagnostic_context();
+    let guard = context.set_diagnostic_handler_scoped(|_| {});
+    assert_ne!(context.get_diagnostic_context(), previous);
+    drop(guard);
+    assert_eq!(context.get_diagnostic_context(), previous);
+}